@@ -0,0 +1,58 @@
+//! Measures how fast each codec encodes and decodes a large nested value.
+//!
+//! Only the codecs enabled by features are measured, so run it with
+//! `cargo bench --bench codecs --features msgpack,cbor` to compare them all.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use caat_rust::{Codec, Value};
+
+const ROWS: usize = 10_000;
+const RUNS: usize = 20;
+
+/// A table of records, mostly numbers, like the results of a query.
+fn table() -> Value {
+    let rows = (0..ROWS).map(|i| {
+        let mut row = HashMap::new();
+        row.insert("id".to_string(), Value::Integer(i as i64));
+        row.insert("name".to_string(), Value::from(format!("row {}", i)));
+        row.insert("score".to_string(), Value::Float(i as f64 / 7.0));
+        row.insert("active".to_string(), Value::Boolean(i % 3 == 0));
+        row.insert("samples".to_string(), Value::from((0..16).map(|j| (i * j) as f64 * 0.5).collect::<Vec<f64>>()));
+        row.insert("checksum".to_string(), Value::Bytes((0..32).map(|j| (i + j) as u8).collect()));
+        Value::Map(row, None)
+    });
+    Value::from(rows.collect::<Vec<Value>>())
+}
+
+/// The median time of `RUNS` runs of `f`.
+fn median<T>(mut f: impl FnMut() -> T) -> Duration {
+    let mut samples = (0..RUNS).map(|_| {
+        let start = Instant::now();
+        std::hint::black_box(f());
+        start.elapsed()
+    }).collect::<Vec<_>>();
+    samples.sort();
+    samples[RUNS / 2]
+}
+
+fn main() {
+    let value = table();
+    println!("{} rows, median of {} runs", ROWS, RUNS);
+    println!("{:<8} {:>10} {:>12} {:>12} {:>12} {:>12}", "codec", "size", "encode", "MB/s", "decode", "MB/s");
+    for codec in Codec::AVAILABLE {
+        let encoded = codec.encode(&value);
+        let megabytes = encoded.len() as f64 / 1e6;
+        let encode = median(|| codec.encode(&value));
+        let decode = median(|| codec.decode(&encoded).unwrap());
+        println!(
+            "{:<8} {:>10} {:>12.3?} {:>12.1} {:>12.3?} {:>12.1}",
+            codec.name(),
+            encoded.len(),
+            encode,
+            megabytes / encode.as_secs_f64(),
+            decode,
+            megabytes / decode.as_secs_f64(),
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! Measures the round-trip latency of a call to a callee that returns at once.
+//!
+//! The callee is an example, so build those first:
+//! `cargo build --release --examples && cargo bench --bench round_trip`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::time::{Duration, Instant};
+use caat_rust::{Caat, ForeignFunction, Value};
+
+const WARMUP: usize = 10;
+const CALLS: usize = 200;
+
+fn main() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    let args = [Value::from("ping"), Value::Integer(1)];
+
+    for _ in 0..WARMUP {
+        echo.try_call(&args).unwrap();
+    }
+
+    let mut samples = Vec::with_capacity(CALLS);
+    for _ in 0..CALLS {
+        let start = Instant::now();
+        echo.try_call(&args).unwrap();
+        samples.push(start.elapsed());
+    }
+    samples.sort();
+
+    let total = samples.iter().sum::<Duration>();
+    println!("round trip over {} calls", CALLS);
+    println!("  mean {:>10.3?}", total / CALLS as u32);
+    println!("  min  {:>10.3?}", samples[0]);
+    println!("  p50  {:>10.3?}", samples[CALLS / 2]);
+    println!("  p99  {:>10.3?}", samples[CALLS * 99 / 100]);
+    println!("  max  {:>10.3?}", samples[CALLS - 1]);
+}
@@ -0,0 +1,176 @@
+//! Procedural macros for `caat_rust`, re-exported from there. Use them through
+//! `caat_rust` rather than depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Expr, ExprLit, FnArg, ItemFn, Lit, LitStr, Meta, MetaNameValue, Pat, Path, ReturnType, Type};
+
+/// Turns a function into the `main` of a CAAT callee.
+///
+/// The parameters are decoded from `CAAT_ARGS`, each through its
+/// `TryFrom<Value>` impl, and the return value is handed back to the caller
+/// with `callee::return_value`. A `Result` return type is unwrapped: `Ok`
+/// becomes the return value and `Err` becomes a `Value::Failure` holding the
+/// error's `Display` text. Wrong arity or argument types are reported as a
+/// failure too.
+///
+/// The program also answers signature requests (see `caat_rust::signature`)
+/// with its parameter names and types, its doc comment as the description
+/// and its crate version.
+///
+/// Run from a shell, the program takes its parameters from the command line
+/// instead and prints its result.
+///
+/// ```ignore
+/// #[caat_rust::main]
+/// fn main(name: String, count: i64) -> Result<Vec<String>, MyError> {
+///     // ...
+/// }
+/// ```
+///
+/// The generated code refers to `::caat_rust`. When the crate is renamed or
+/// re-exported, pass the path it is reachable under instead:
+///
+/// ```ignore
+/// #[caat::main(crate = "caat")]
+/// fn main(name: String) -> String {
+///     // ...
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut krate: Path = syn::parse_quote!(::caat_rust);
+    let arguments = syn::meta::parser(|meta| {
+        if meta.path.is_ident("crate") {
+            krate = meta.value()?.parse::<LitStr>()?.parse()?;
+            Ok(())
+        } else {
+            Err(meta.error("unknown argument, #[caat_rust::main] only takes `crate = \"...\"`"))
+        }
+    });
+    parse_macro_input!(attr with arguments);
+    let function = parse_macro_input!(item as ItemFn);
+    match expand(function, &krate) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(function: ItemFn, krate: &Path) -> syn::Result<proc_macro2::TokenStream> {
+    let signature = &function.sig;
+    if let Some(asyncness) = &signature.asyncness {
+        return Err(syn::Error::new_spanned(asyncness, "a CAAT main function cannot be async"));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&signature.generics, "a CAAT main function cannot be generic"));
+    }
+
+    let mut names = Vec::new();
+    let mut decoders = Vec::new();
+    let mut params = Vec::new();
+    for input in &signature.inputs {
+        let typed = match input {
+            FnArg::Typed(typed) => typed,
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(receiver, "a CAAT main function cannot take self"));
+            }
+        };
+        let name = match &*typed.pat {
+            Pat::Ident(pat) if pat.subpat.is_none() => &pat.ident,
+            pat => return Err(syn::Error::new_spanned(pat, "parameters of a CAAT main function must be plain names")),
+        };
+        let ty = &typed.ty;
+        let label = name.to_string();
+        decoders.push(quote! {
+            let #name = params.next::<#ty>(#label)?;
+        });
+        params.push(quote! {
+            #krate::signature::Param {
+                name: #label.to_string(),
+                value_type: (&#krate::signature::Probe::<#ty>::new()).value_type(),
+            }
+        });
+        names.push(name);
+    }
+
+    let inner = &function.sig.ident;
+    let call = quote! { #inner(#(#names),*) };
+    let result = match &signature.output {
+        ReturnType::Default => quote! {
+            #call;
+            #krate::Value::Null
+        },
+        ReturnType::Type(_, ty) if is_result(ty) => quote! {
+            match #call {
+                Ok(value) => #krate::Value::from(value),
+                Err(error) => #krate::Value::Failure(error.to_string()),
+            }
+        },
+        ReturnType::Type(..) => quote! {
+            #krate::Value::from(#call)
+        },
+    };
+
+    let returns = match &signature.output {
+        ReturnType::Default => quote! { #krate::signature::ValueType::Null },
+        ReturnType::Type(_, ty) => quote! { (&#krate::signature::Probe::<#ty>::new()).value_type() },
+    };
+    let description = match description(&function.attrs) {
+        Some(text) => quote! { Some(#text.to_string()) },
+        None => quote! { None },
+    };
+
+    let main = syn::Ident::new("main", Span::call_site());
+    Ok(quote! {
+        fn #main() {
+            #function
+
+            #krate::callee::run(|| {
+                #[allow(unused_imports)]
+                use #krate::signature::{DescribeKnown as _, DescribeUnknown as _};
+                #krate::signature::Signature {
+                    params: vec![#(#params),*],
+                    returns: #returns,
+                    description: #description,
+                    version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                }
+            }, |params| {
+                #(#decoders)*
+                Ok(#result)
+            })
+        }
+    })
+}
+
+/// Joins the doc comments of the function into the description of its
+/// signature.
+fn description(attrs: &[Attribute]) -> Option<String> {
+    let lines = attrs.iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(s), .. }), .. }) => Some(s.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect::<Vec<String>>();
+    let text = lines.join("\n").trim().to_string();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Whether a return type names `Result`, in which case its `Err` side becomes
+/// a failure. The macro only sees tokens, so this goes by the last segment of
+/// the path and aliases such as `io::Result<T>` count as well.
+fn is_result(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.qself.is_none()
+            && path.path.segments.last().is_some_and(|segment| segment.ident == "Result"),
+        Type::Paren(paren) => is_result(&paren.elem),
+        Type::Group(group) => is_result(&group.elem),
+        _ => false,
+    }
+}
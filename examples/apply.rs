@@ -0,0 +1,16 @@
+//! Calls the function it is given on each of its other arguments and returns
+//! the results as a list.
+//!
+//! The integration tests use this as a callee that calls back into its caller.
+
+use caat_rust::{Caat, Value};
+
+fn main() {
+    let mut args = caat_rust::args();
+    let function = match args.next() {
+        Some(Value::CAATFunction(function)) => function,
+        other => caat_rust::return_caat!(Value::Failure(format!("expected a function, got {:?}", other))),
+    };
+    let results = args.map(|arg| function.call(&[arg])).collect::<Vec<Value>>();
+    caat_rust::return_caat!(results);
+}
@@ -0,0 +1,34 @@
+//! Yields the numbers from 1 up to its first argument, then returns "done".
+//! With a second argument of `true` it exits with code 3 instead of
+//! returning. A string second argument names a file that it keeps up to date
+//! with its pid and the last number it yielded, separated by a space.
+//!
+//! The integration tests use this as a callee that streams its results.
+
+use caat_rust::Value;
+
+fn main() {
+    let mut args = caat_rust::args();
+    let n = args.next().and_then(|n| i64::try_from(n).ok()).unwrap_or(0);
+    let (fail, report) = match args.next() {
+        Some(Value::String(report)) => (false, Some(report)),
+        other => (other.is_some(), None),
+    };
+    let progress = |i: i64| {
+        if let Some(report) = &report {
+            std::fs::write(report, format!("{} {}", std::process::id(), i)).unwrap();
+        }
+    };
+    progress(0);
+    for i in 1..=n {
+        if let Err(e) = caat_rust::yield_value(Value::Integer(i)) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        progress(i);
+    }
+    if fail {
+        std::process::exit(3);
+    }
+    caat_rust::return_caat!("done");
+}
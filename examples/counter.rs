@@ -0,0 +1,19 @@
+//! Counts the calls it has answered and returns the count along with its
+//! arguments.
+//!
+//! The integration tests use this as a worker: the count keeps going up for
+//! as long as the same process answers, and a function argument is called
+//! with the count.
+
+use caat_rust::Value;
+
+fn main() {
+    let mut calls = 0;
+    caat_rust::serve_loop(|args| {
+        calls += 1;
+        match args {
+            [Value::CAATFunction(function)] => function.call(&[Value::from(calls)]),
+            _ => Value::from(vec![Value::from(calls), Value::from(args.to_vec())]),
+        }
+    });
+}
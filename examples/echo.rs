@@ -0,0 +1,10 @@
+//! Returns its arguments as a list.
+//!
+//! The integration tests use this as a callee.
+
+use caat_rust::Value;
+
+fn main() {
+    let args = caat_rust::args().collect::<Vec<Value>>();
+    caat_rust::return_caat!(args);
+}
@@ -0,0 +1,21 @@
+//! Returns the even integers of the stream or list it is given, stopping
+//! after as many as its optional second argument says.
+//!
+//! The integration tests use this as a callee that reads a streamed argument.
+
+use caat_rust::stream::Stream;
+use caat_rust::Value;
+
+fn main() {
+    let mut args = caat_rust::args();
+    let input = match args.next().map(Stream::try_from) {
+        Some(Ok(input)) => input,
+        _ => caat_rust::return_caat!(Value::Failure("expected a stream".to_string())),
+    };
+    let limit = args.next().and_then(|limit| u32::try_from(limit).ok()).map_or(usize::MAX, |limit| limit as usize);
+    let evens = input
+        .filter(|value| matches!(value, Value::Integer(i) if i % 2 == 0))
+        .take(limit)
+        .collect::<Vec<Value>>();
+    caat_rust::return_caat!(evens);
+}
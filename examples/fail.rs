@@ -0,0 +1,33 @@
+//! Fails in the way its first argument names, with its second argument as
+//! the message.
+//!
+//! The integration tests use this as a callee that does not succeed.
+
+use caat_rust::Value;
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let message = args.get(1).cloned().unwrap_or_default();
+    match args.first().map(String::as_str) {
+        Some("failure") => caat_rust::return_caat!(Value::Failure(message)),
+        Some("data") => {
+            let data = Value::from(vec![("code".to_string(), Value::Integer(42))]);
+            match caat_rust::callee::return_failure(message, data) {
+                Ok(never) => match never {},
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some("exit") => {
+            eprintln!("{}", message);
+            std::process::exit(3);
+        }
+        Some("abort") => {
+            eprintln!("{}", message);
+            std::process::abort();
+        }
+        other => caat_rust::return_caat!(Value::Failure(format!("unknown way to fail: {:?}", other))),
+    }
+}
@@ -0,0 +1,24 @@
+//! Greets `name` `count` times.
+//!
+//! The integration tests use this as a callee written with
+//! `#[caat_rust::main]`.
+
+use std::fmt;
+
+#[derive(Debug)]
+struct NegativeCount(i64);
+
+impl fmt::Display for NegativeCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot greet {} times", self.0)
+    }
+}
+
+/// Greets someone a number of times.
+#[caat_rust::main]
+fn main(name: String, count: i64) -> Result<Vec<String>, NegativeCount> {
+    if count < 0 {
+        return Err(NegativeCount(count));
+    }
+    Ok((0..count).map(|_| format!("Hello, {}!", name)).collect())
+}
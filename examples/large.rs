@@ -0,0 +1,12 @@
+//! Returns a string of as many characters as its argument asks for.
+//!
+//! The integration tests use this as a callee with a large result.
+
+/// Mixes in characters JSON has to escape and ones that take several bytes.
+const PATTERN: &str = "The \"quick\" brown fox\\jumps\nover the lazy dog 🦀 ";
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let length = args.first().and_then(|length| length.parse().ok()).unwrap_or(0);
+    caat_rust::return_caat!(PATTERN.chars().cycle().take(length).collect::<String>());
+}
@@ -0,0 +1,17 @@
+//! Writes the bytes given in hex as its first argument to the caller's
+//! socket as they are, then exits.
+//!
+//! The integration tests use this as a callee that speaks the wire protocol
+//! by hand.
+
+use std::io::Write;
+use interprocess::local_socket::LocalSocketStream;
+
+fn main() {
+    let hex = std::env::args().nth(1).unwrap_or_default();
+    let bytes = (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect::<Vec<u8>>();
+    let mut stream = LocalSocketStream::connect(std::env::var("CAAT_SOCKET").unwrap()).unwrap();
+    stream.write_all(&bytes).unwrap();
+}
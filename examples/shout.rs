@@ -0,0 +1,11 @@
+//! Returns its argument in upper case.
+//!
+//! The integration tests use this as a callee written with
+//! `#[caat_rust::main]` under another name for the crate.
+
+use caat_rust as caat;
+
+#[caat::main(crate = "caat")]
+fn main(text: String) -> String {
+    text.to_uppercase()
+}
@@ -0,0 +1,26 @@
+//! Sleeps for the number of milliseconds given as its first argument, then
+//! returns `"woke"`.
+//!
+//! If a second argument is given, its pid and socket path are written to that
+//! file first, one per line. A third argument of `ignore-term` makes it ignore
+//! `SIGTERM`. The integration tests use this as a callee that takes too long.
+
+use std::time::Duration;
+use caat_rust::Value;
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let millis = args.first().and_then(|millis| millis.parse().ok()).unwrap_or(0);
+    if args.get(2).map(String::as_str) == Some("ignore-term") {
+        #[cfg(unix)]
+        unsafe {
+            libc::signal(libc::SIGTERM, libc::SIG_IGN);
+        }
+    }
+    if let Some(report) = args.get(1) {
+        let socket = std::env::var("CAAT_SOCKET").unwrap_or_default();
+        std::fs::write(report, format!("{}\n{}\n", std::process::id(), socket)).unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(millis));
+    caat_rust::return_caat!(Value::from("woke"));
+}
@@ -0,0 +1,9 @@
+//! Splits each of its lines into words.
+//!
+//! The integration tests use this as a callee written with
+//! `#[caat_rust::main]` that takes and returns vectors.
+
+#[caat_rust::main]
+fn main(lines: Vec<String>) -> Vec<Vec<String>> {
+    lines.iter().map(|line| line.split_whitespace().map(str::to_string).collect()).collect()
+}
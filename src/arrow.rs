@@ -0,0 +1,265 @@
+//! Arrow combinators for building pipelines out of `Caat` functions.
+//!
+//! Every combinator returns a new `Caat`, so a pipeline can be combined
+//! further, called like any other function or wrapped in
+//! `Value::CAATFunction` with `CaatExt::into_value`.
+//!
+//! Combinators that work on pairs take them either as two arguments or as a
+//! single two element list, which is what `then` passes on when the previous
+//! step returned a pair, and return pairs as two element lists. A
+//! `Value::Failure` from any step is returned as it is, without running the
+//! steps after it. `try_call` does the same with errors, so the structured
+//! error of a failed step, such as `CaatError::SpawnFailed`, is kept.
+
+use std::fmt;
+use std::sync::Arc;
+use crate::{Caat, CaatError, ForeignFunction, Value};
+
+/// The combinators, available on every `Caat`.
+pub trait CaatExt: Caat + Sized {
+    /// Calls `self`, then `next` with the result (`>>>`).
+    fn then<G: Caat>(self, next: G) -> Then<Self, G> {
+        Then { first: self, second: next }
+    }
+
+    /// The same as `then`.
+    fn compose<G: Caat>(self, next: G) -> Then<Self, G> {
+        self.then(next)
+    }
+
+    /// Applies `self` to the first element of a pair and keeps the second.
+    fn first(self) -> First<Self> {
+        First(self)
+    }
+
+    /// Applies `self` to the second element of a pair and keeps the first.
+    fn second(self) -> Second<Self> {
+        Second(self)
+    }
+
+    /// Applies `self` to the first element of a pair and `other` to the
+    /// second (`***`).
+    fn split<G: Caat>(self, other: G) -> Split<Self, G> {
+        Split { left: self, right: other }
+    }
+
+    /// Calls `self` and `other` with the same arguments and pairs the results
+    /// (`&&&`).
+    fn fanout<G: Caat>(self, other: G) -> Fanout<Self, G> {
+        Fanout { left: self, right: other }
+    }
+
+    /// Calls `self`, then `map` on the result in process.
+    fn map_value<M>(self, map: M) -> MapValue<Self, M>
+    where M: Fn(Value) -> Value {
+        MapValue { function: self, map }
+    }
+
+    /// Wraps the function in a `Value::CAATFunction`.
+    fn into_value(self) -> Value
+    where Self: Send + Sync + 'static {
+        Value::CAATFunction(Arc::new(self))
+    }
+}
+
+impl<T: Caat> CaatExt for T {}
+
+impl<T: Caat + ?Sized> Caat for Arc<T> {
+    fn call(&self, args: &[Value]) -> Value {
+        (**self).call(args)
+    }
+
+    fn as_foreign(&self) -> Option<&ForeignFunction> {
+        (**self).as_foreign()
+    }
+
+    fn callback_id(&self) -> Option<usize> {
+        (**self).callback_id()
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        (**self).try_call(args)
+    }
+}
+
+/// Splits the arguments of a pair combinator into its two elements, or
+/// returns the message of the failure to report.
+fn pair(args: &[Value]) -> Result<(Value, Value), String> {
+    match args {
+        [a, b] => Ok((a.clone(), b.clone())),
+        [Value::List(l)] if l.len() == 2 => Ok((l[0].clone(), l[1].clone())),
+        [Value::Failure(message)] => Err(message.clone()),
+        _ => Err(format!("expected a pair, got {} arguments", args.len())),
+    }
+}
+
+/// `pair` for `try_call`.
+fn try_pair(args: &[Value]) -> Result<(Value, Value), CaatError> {
+    pair(args).map_err(|message| CaatError::RemoteFailure { message, data: None })
+}
+
+/// Reports a `Value::Failure` as an error, as `Caat::try_call` does.
+fn into_result(value: Value) -> Result<Value, CaatError> {
+    match value {
+        Value::Failure(message) => Err(CaatError::RemoteFailure { message, data: None }),
+        value => Ok(value),
+    }
+}
+
+/// Joins two results into a pair, unless one of them is a failure.
+fn join(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (failure @ Value::Failure(_), _) | (_, failure @ Value::Failure(_)) => failure,
+        (a, b) => Value::from(vec![a, b]),
+    }
+}
+
+pub struct Then<F, G> {
+    first: F,
+    second: G,
+}
+
+impl<F: Caat, G: Caat> Caat for Then<F, G> {
+    fn call(&self, args: &[Value]) -> Value {
+        match self.first.call(args) {
+            failure @ Value::Failure(_) => failure,
+            value => self.second.call(&[value]),
+        }
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        let value = self.first.try_call(args)?;
+        self.second.try_call(&[value])
+    }
+}
+
+impl<F: Caat, G: Caat> fmt::Display for Then<F, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({} >>> {})", self.first, self.second)
+    }
+}
+
+pub struct First<F>(F);
+
+impl<F: Caat> Caat for First<F> {
+    fn call(&self, args: &[Value]) -> Value {
+        match pair(args) {
+            Ok((a, b)) => join(self.0.call(&[a]), b),
+            Err(message) => Value::Failure(message),
+        }
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        let (a, b) = try_pair(args)?;
+        into_result(join(self.0.try_call(&[a])?, b))
+    }
+}
+
+impl<F: Caat> fmt::Display for First<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "first({})", self.0)
+    }
+}
+
+pub struct Second<F>(F);
+
+impl<F: Caat> Caat for Second<F> {
+    fn call(&self, args: &[Value]) -> Value {
+        match pair(args) {
+            Ok((failure @ Value::Failure(_), _)) => failure,
+            Ok((a, b)) => join(a, self.0.call(&[b])),
+            Err(message) => Value::Failure(message),
+        }
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        let (a, b) = try_pair(args)?;
+        let a = into_result(a)?;
+        into_result(join(a, self.0.try_call(&[b])?))
+    }
+}
+
+impl<F: Caat> fmt::Display for Second<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "second({})", self.0)
+    }
+}
+
+pub struct Split<F, G> {
+    left: F,
+    right: G,
+}
+
+impl<F: Caat, G: Caat> Caat for Split<F, G> {
+    fn call(&self, args: &[Value]) -> Value {
+        let (a, b) = match pair(args) {
+            Ok(pair) => pair,
+            Err(message) => return Value::Failure(message),
+        };
+        match self.left.call(&[a]) {
+            failure @ Value::Failure(_) => failure,
+            a => join(a, self.right.call(&[b])),
+        }
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        let (a, b) = try_pair(args)?;
+        let a = self.left.try_call(&[a])?;
+        into_result(join(a, self.right.try_call(&[b])?))
+    }
+}
+
+impl<F: Caat, G: Caat> fmt::Display for Split<F, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({} *** {})", self.left, self.right)
+    }
+}
+
+pub struct Fanout<F, G> {
+    left: F,
+    right: G,
+}
+
+impl<F: Caat, G: Caat> Caat for Fanout<F, G> {
+    fn call(&self, args: &[Value]) -> Value {
+        match self.left.call(args) {
+            failure @ Value::Failure(_) => failure,
+            a => join(a, self.right.call(args)),
+        }
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        let a = self.left.try_call(args)?;
+        into_result(join(a, self.right.try_call(args)?))
+    }
+}
+
+impl<F: Caat, G: Caat> fmt::Display for Fanout<F, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({} &&& {})", self.left, self.right)
+    }
+}
+
+pub struct MapValue<F, M> {
+    function: F,
+    map: M,
+}
+
+impl<F: Caat, M: Fn(Value) -> Value> Caat for MapValue<F, M> {
+    fn call(&self, args: &[Value]) -> Value {
+        match self.function.call(args) {
+            failure @ Value::Failure(_) => failure,
+            value => (self.map)(value),
+        }
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        into_result((self.map)(self.function.try_call(args)?))
+    }
+}
+
+impl<F: Caat, M> fmt::Display for MapValue<F, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "map({})", self.function)
+    }
+}
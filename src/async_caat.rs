@@ -0,0 +1,106 @@
+//! Calling foreign functions from async code, enabled by the `tokio` feature.
+//!
+//! The callee is driven by `tokio::process` and its answer is read from a
+//! `tokio::net::UnixListener`, so waiting for it does not block a thread.
+//! Dropping the future of a call cancels it: the callee is killed and its
+//! socket removed. Timeouts are left to `tokio::time::timeout`, which works by
+//! dropping the future.
+//!
+//! Callbacks and streamed arguments are served as for blocking calls, on
+//! threads of their own, since the functions and iterators behind them are
+//! not async. Working out the result may wait on the callee's stderr, so it
+//! runs on the blocking thread pool too.
+
+use std::future::Future;
+use std::sync::Arc;
+use tokio::net::{UnixListener, UnixStream};
+use crate::callback::Nesting;
+use crate::frame::{self, FrameKind, Message};
+use crate::process::StderrCapture;
+use crate::socket;
+use crate::{CaatError, ForeignFunction, Value};
+
+/// The async counterpart of `Caat`.
+pub trait AsyncCaat {
+    fn call(&self, args: &[Value]) -> impl Future<Output = Result<Value, CaatError>> + Send;
+}
+
+impl AsyncCaat for ForeignFunction {
+    async fn call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        self.check_args(args)?;
+        let nesting = Nesting::child(None)?;
+        let socket_path = socket::socket_path().map_err(CaatError::SocketBind)?;
+        let socket_file = AsyncSocketFile(socket_path);
+        let listener = UnixListener::bind(&socket_file.0).map_err(CaatError::SocketBind)?;
+
+        let (command, callbacks) = self.command(args, &socket_file.0, nesting);
+        let callbacks = Arc::new(callbacks);
+        let mut command = tokio::process::Command::from(command);
+        command.kill_on_drop(true);
+        let mut child = command.spawn().map_err(CaatError::SpawnFailed)?;
+        let stderr = child.stderr.take()
+            .and_then(|stderr| stderr.into_owned_fd().ok())
+            .map(std::fs::File::from);
+        let stderr = StderrCapture::from_reader(stderr);
+
+        let accept = async {
+            loop {
+                let (stream, _) = listener.accept().await.map_err(CaatError::Accept)?;
+                let mut stream = stream.into_std()
+                    .and_then(|stream| stream.set_nonblocking(false).map(|_| stream))
+                    .map_err(CaatError::Accept)?;
+                let (message, stream) = tokio::task::spawn_blocking(move || (frame::read_message(&mut stream), stream))
+                    .await
+                    .map_err(|e| CaatError::ProtocolError(format!("failed to read from socket: {}", e)))?;
+                match message {
+                    Ok(Message::Frame(request)) if request.kind == FrameKind::CallbackRequest => {
+                        let callbacks = callbacks.clone();
+                        std::thread::spawn(move || callbacks.serve(stream, request));
+                    }
+                    Ok(Message::Frame(request)) if request.kind == FrameKind::StreamRequest => {
+                        let callbacks = callbacks.clone();
+                        std::thread::spawn(move || callbacks.send_stream(stream, request));
+                    }
+                    message => return message,
+                }
+            }
+        };
+        tokio::pin!(accept);
+
+        let mut status = None;
+        let mut response = None;
+        let (status, response) = loop {
+            tokio::select! {
+                message = &mut accept, if response.is_none() => response = Some(message?),
+                exit = child.wait(), if status.is_none() => {
+                    let exit = exit.map_err(|e| CaatError::ProtocolError(format!("failed to wait for command: {}", e)))?;
+                    if response.is_none() {
+                        // The callee may have exited without ever connecting.
+                        let _ = UnixStream::connect(&socket_file.0).await;
+                    }
+                    status = Some(exit);
+                }
+            }
+            match (status, response) {
+                (Some(exit), Some(message)) => break (exit, message),
+                (exit, message) => (status, response) = (exit, message),
+            }
+        };
+        let callbacks = callbacks.clone();
+        tokio::task::spawn_blocking(move || ForeignFunction::finish(status, &response, stderr, &callbacks))
+            .await
+            .map_err(|e| CaatError::ProtocolError(format!("failed to read the result: {}", e)))?
+    }
+}
+
+/// Removes the socket of an async call when the call ends or is dropped.
+///
+/// Unlike `SocketFile` it does not connect to the socket first, which could
+/// block the runtime, since no thread is left waiting to accept on it.
+struct AsyncSocketFile(String);
+
+impl Drop for AsyncSocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,238 @@
+//! Functions passed to a callee that run in the caller.
+//!
+//! A `Value::CAATFunction` that is not a command, such as a `NativeFunction`
+//! or a pipeline built with `CaatExt`, cannot be started by the callee. When
+//! one is passed as an argument the caller gives it an id and encodes it with
+//! a `"callback"` field next to its name. The callee sees it as a `Callback`,
+//! and calling that sends a request over the call's socket, which the caller
+//! answers by running the function while it waits for the call to return.
+//!
+//! Callbacks may start further calls, which may take callbacks of their own.
+//! The depth of such nesting is tracked in `CAAT_DEPTH` and limited by
+//! `CallOptions::max_depth`, so runaway recursion between programs ends with
+//! `CaatError::DepthExceeded` rather than exhausting the machine.
+
+use std::cell::Cell;
+use std::fmt;
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+use interprocess::local_socket::LocalSocketStream;
+use json::JsonValue;
+use crate::codec;
+use crate::frame::{self, ContentType, FrameKind, Frame, Message};
+use crate::stream::Stream;
+use crate::{Caat, CaatError, ForeignFunction, Value, SOCKET_VAR};
+
+pub(crate) const DEPTH_VAR: &str = "CAAT_DEPTH";
+pub(crate) const MAX_DEPTH_VAR: &str = "CAAT_MAX_DEPTH";
+/// How deeply calls may nest when neither `CallOptions::max_depth` nor a
+/// caller further up says otherwise.
+pub const DEFAULT_MAX_DEPTH: u32 = 16;
+
+thread_local! {
+    /// The nesting of the callback this thread is running, if any.
+    static CALLBACK_DEPTH: Cell<Option<Nesting>> = const { Cell::new(None) };
+}
+
+/// How deeply the current code is nested in calls, and how deep it may go.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Nesting {
+    pub(crate) depth: u32,
+    pub(crate) max_depth: u32,
+}
+
+impl Nesting {
+    /// The nesting of the running callback, or else of this process as set
+    /// by our caller.
+    pub(crate) fn current() -> Nesting {
+        if let Some(nesting) = CALLBACK_DEPTH.with(Cell::get) {
+            return nesting;
+        }
+        let var = |name: &str| std::env::var(name).ok().and_then(|s| s.parse::<u32>().ok());
+        Nesting {
+            depth: var(DEPTH_VAR).unwrap_or(0),
+            max_depth: var(MAX_DEPTH_VAR).unwrap_or(DEFAULT_MAX_DEPTH),
+        }
+    }
+
+    /// The nesting of a call started from here, or an error if it would be
+    /// too deep. `max_depth` can only lower the inherited limit.
+    pub(crate) fn child(max_depth: Option<u32>) -> Result<Nesting, CaatError> {
+        let current = Nesting::current();
+        let max_depth = max_depth.map_or(current.max_depth, |max| max.min(current.max_depth));
+        if current.depth >= max_depth {
+            return Err(CaatError::DepthExceeded(max_depth));
+        }
+        Ok(Nesting { depth: current.depth + 1, max_depth })
+    }
+
+    /// Runs `f` as if nested this deeply.
+    fn enter<T, F: FnOnce() -> T>(self, f: F) -> T {
+        let outer = CALLBACK_DEPTH.with(|depth| depth.replace(Some(self)));
+        let result = f();
+        CALLBACK_DEPTH.with(|depth| depth.set(outer));
+        result
+    }
+}
+
+/// The functions a call hands to its callee as callbacks, and the streams it
+/// hands over as arguments, indexed by id.
+///
+/// More are registered while the call runs, when a callback returns a
+/// function or a stream, so both lists are behind a lock.
+#[derive(Default)]
+pub(crate) struct Callbacks {
+    functions: Mutex<Vec<Arc<dyn Caat + Send + Sync>>>,
+    streams: Mutex<Vec<Stream>>,
+}
+
+impl Callbacks {
+    pub(crate) fn register(&self, function: Arc<dyn Caat + Send + Sync>) -> usize {
+        let mut functions = self.functions.lock().unwrap_or_else(|e| e.into_inner());
+        functions.push(function);
+        functions.len() - 1
+    }
+
+    pub(crate) fn get(&self, id: usize) -> Option<Arc<dyn Caat + Send + Sync>> {
+        self.functions.lock().unwrap_or_else(|e| e.into_inner()).get(id).cloned()
+    }
+
+    pub(crate) fn register_stream(&self, stream: Stream) -> usize {
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        streams.push(stream);
+        streams.len() - 1
+    }
+
+    pub(crate) fn stream(&self, id: usize) -> Option<Stream> {
+        self.streams.lock().unwrap_or_else(|e| e.into_inner()).get(id).cloned()
+    }
+
+    /// Runs the callback a request asks for. Functions and streams among its
+    /// arguments are the ones registered here.
+    fn answer(&self, request: &[u8]) -> Value {
+        let json = match std::str::from_utf8(request).ok().and_then(|s| json::parse(s).ok()) {
+            Some(json) => json,
+            None => return Value::Failure("malformed callback request".to_string()),
+        };
+        let function = match json["callback"].as_usize().and_then(|id| self.get(id)) {
+            Some(function) => function,
+            None => return Value::Failure(format!("no callback with id {}", json["callback"])),
+        };
+        let mut args = Vec::new();
+        for arg in json["args"].members() {
+            match Value::decode(arg, Some(self)) {
+                Some(arg) => args.push(arg),
+                None => return Value::Failure("malformed callback arguments".to_string()),
+            }
+        }
+        let nesting = Nesting {
+            depth: json["depth"].as_u32().unwrap_or(1),
+            max_depth: json["max_depth"].as_u32().unwrap_or(DEFAULT_MAX_DEPTH),
+        };
+        nesting.enter(|| function.call(&args))
+    }
+
+    /// Answers the callback requests on one connection, starting with
+    /// `request`, until the callee closes it. Functions and streams in the
+    /// results are registered so the callee can use them too.
+    pub(crate) fn serve<S: Read + Write>(&self, mut stream: S, mut request: Frame) {
+        loop {
+            let response = self.answer(&request.payload).encode(Some(self)).dump();
+            let written = frame::write_frame(
+                &mut stream, request.version, ContentType::Json, FrameKind::CallbackResponse, response.as_bytes(),
+            );
+            if written.is_err() {
+                return;
+            }
+            request = match frame::read_message(&mut stream) {
+                Ok(Message::Frame(frame)) if frame.kind == FrameKind::CallbackRequest => frame,
+                _ => return,
+            };
+        }
+    }
+
+    /// Sends the values of the stream a request asks for as `Yield` frames,
+    /// ending with a `Return`. Stops early if the callee hangs up. Functions
+    /// and streams in the values are registered like those of arguments.
+    pub(crate) fn send_stream<S: Write>(&self, mut connection: S, request: Frame) {
+        let id = std::str::from_utf8(&request.payload).ok()
+            .and_then(|s| json::parse(s).ok())
+            .and_then(|json| json["stream"].as_usize());
+        let values: Box<dyn Iterator<Item = Value>> = match id.and_then(|id| self.stream(id)) {
+            Some(stream) => Box::new(stream),
+            None => Box::new(std::iter::once(Value::Failure(format!("no stream with id {:?}", id)))),
+        };
+        let mut send = |kind, value: Value| {
+            frame::write_frame(&mut connection, request.version, ContentType::Json, kind, value.encode(Some(self)).dump().as_bytes())
+        };
+        for value in values {
+            if send(FrameKind::Yield, value).is_err() {
+                return;
+            }
+        }
+        let _ = send(FrameKind::Return, Value::Null);
+    }
+}
+
+/// A function the caller of this process passed in, run by the caller when
+/// called.
+pub struct Callback {
+    id: usize,
+    name: String,
+    socket: String,
+}
+
+impl Callback {
+    /// Builds the callback for an argument decoded in this process. Outside of
+    /// a call there is no caller to run it, so it falls back to being a
+    /// command.
+    pub(crate) fn decode(id: usize, name: &str) -> Value {
+        match std::env::var(SOCKET_VAR) {
+            Ok(socket) => Value::CAATFunction(Arc::new(Callback { id, name: name.to_string(), socket })),
+            Err(_) => Value::CAATFunction(Arc::new(ForeignFunction::new(name))),
+        }
+    }
+
+    fn request(&self, args: &[Value]) -> Result<Value, CaatError> {
+        let version = frame::negotiated_version()
+            .filter(|version| *version >= frame::CALLBACK_VERSION)
+            .ok_or_else(|| CaatError::ProtocolError("the caller does not support callbacks".to_string()))?;
+        let nesting = Nesting::current();
+        let mut request = JsonValue::new_object();
+        request["callback"] = self.id.into();
+        request["args"] = JsonValue::Array(args.iter().map(Value::to_json_value).collect());
+        request["depth"] = nesting.depth.into();
+        request["max_depth"] = nesting.max_depth.into();
+
+        let mut stream = LocalSocketStream::connect(self.socket.as_str())
+            .map_err(|e| CaatError::ProtocolError(format!("failed to connect to caller: {}", e)))?;
+        frame::write_frame(&mut stream, version, ContentType::Json, FrameKind::CallbackRequest, request.dump().as_bytes())
+            .map_err(|e| CaatError::ProtocolError(format!("failed to send callback request: {}", e)))?;
+        match frame::read_message(&mut stream)? {
+            Message::Frame(frame) if frame.kind == FrameKind::CallbackResponse => {
+                codec::decode_frame(&frame, None)
+            }
+            _ => Err(CaatError::ProtocolError("the caller did not answer the callback".to_string())),
+        }
+    }
+}
+
+impl Caat for Callback {
+    fn call(&self, args: &[Value]) -> Value {
+        self.try_call(args).unwrap_or_else(Value::from)
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        self.request(args)
+    }
+
+    fn callback_id(&self) -> Option<usize> {
+        Some(self.id)
+    }
+}
+
+impl fmt::Display for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
@@ -0,0 +1,398 @@
+//! The callee side of a CAAT call.
+//!
+//! A program that is started through `ForeignFunction::call` finds the path of
+//! the caller's socket in `CAAT_SOCKET`. Once it is done it writes its return
+//! value to that socket and exits. When the program is started from a shell
+//! instead there is no socket, and the value is printed so it is still useful.
+//!
+//! A program built around `serve_loop` can also be kept running as a worker
+//! by `ForeignFunction::spawn_worker`, answering many calls in one process.
+//!
+//! A program called through `ForeignFunction::call_stream` may hand back any
+//! number of values with `yield_value` before it returns.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::io::prelude::*;
+use std::sync::Mutex;
+use interprocess::local_socket::LocalSocketStream;
+use json::JsonValue;
+use crate::codec::Codec;
+use crate::frame::{self, FrameKind, Message};
+use crate::signature::{Signature, INTROSPECT_ARG, INTROSPECT_VAR};
+use crate::stream::STREAM_VAR;
+use crate::worker::WORKER_VAR;
+use crate::{Value, ARGS_VAR, SOCKET_VAR};
+
+/// An error raised while handing a return value back to the caller.
+#[derive(Debug)]
+pub enum ReturnError {
+    /// The socket named by `CAAT_SOCKET` could not be connected to.
+    Connect(std::io::Error),
+    /// The value could not be written to the socket.
+    Write(std::io::Error),
+    /// A value was yielded, but the caller did not ask for a stream.
+    NotStreaming,
+}
+
+impl fmt::Display for ReturnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReturnError::Connect(e) => write!(f, "failed to connect to caller: {}", e),
+            ReturnError::Write(e) => write!(f, "failed to write return value: {}", e),
+            ReturnError::NotStreaming => write!(f, "the caller does not accept a stream of values"),
+        }
+    }
+}
+
+impl Error for ReturnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReturnError::Connect(e) => Some(e),
+            ReturnError::Write(e) => Some(e),
+            ReturnError::NotStreaming => None,
+        }
+    }
+}
+
+/// The connection values are yielded on, once the first one is.
+static STREAM: Mutex<Option<LocalSocketStream>> = Mutex::new(None);
+
+/// Returns `value` to the caller and exits the process.
+///
+/// When `CAAT_SOCKET` is not set the program was not started by a CAAT caller,
+/// so the value is printed instead: failures go to stderr with exit code 1 and
+/// everything else goes to stdout with exit code 0.
+///
+/// This only returns if the value could not be delivered.
+pub fn return_value(value: Value) -> Result<Infallible, ReturnError> {
+    let socket_path = match std::env::var(SOCKET_VAR) {
+        Ok(s) => s,
+        Err(_) => print_and_exit(&value),
+    };
+    send(&socket_path, |codec| codec.encode(&value))?;
+    std::process::exit(0);
+}
+
+/// Returns a failure with structured `data` attached and exits the process.
+///
+/// The caller sees it as `CaatError::RemoteFailure { message, data }`. Callers
+/// that do not know about `data` still see a plain `Value::Failure`.
+pub fn return_failure<S: Into<String>>(message: S, data: Value) -> Result<Infallible, ReturnError> {
+    let failure = Value::Failure(message.into());
+    let socket_path = match std::env::var(SOCKET_VAR) {
+        Ok(s) => s,
+        Err(_) => print_and_exit(&failure),
+    };
+    send(&socket_path, |codec| codec.encode_failure(&failure, &data))?;
+    std::process::exit(0);
+}
+
+/// Sends one value of a stream to a caller that used
+/// `ForeignFunction::call_stream`.
+///
+/// Values are sent as they are yielded, so the program never holds the whole
+/// result. This blocks while the caller is not keeping up, and fails once the
+/// caller has stopped reading, after which the program should exit. Finish
+/// the stream with `return_value`, whose value the caller gets after the
+/// last yielded one, or just exit.
+///
+/// When `CAAT_SOCKET` is not set the value is printed on a line of its own.
+pub fn yield_value(value: Value) -> Result<(), ReturnError> {
+    let socket_path = match std::env::var(SOCKET_VAR) {
+        Ok(s) => s,
+        Err(_) => {
+            println!("{}", value);
+            return Ok(());
+        }
+    };
+    let version = frame::negotiated_version()
+        .filter(|version| *version >= frame::STREAM_VERSION && std::env::var_os(STREAM_VAR).is_some())
+        .ok_or(ReturnError::NotStreaming)?;
+    let mut connection = STREAM.lock().unwrap_or_else(|e| e.into_inner());
+    let stream = match connection.take() {
+        Some(stream) => connection.insert(stream),
+        None => connection.insert(LocalSocketStream::connect(socket_path).map_err(ReturnError::Connect)?),
+    };
+    let codec = Codec::negotiated();
+    frame::write_frame(stream, version, codec.content_type(), FrameKind::Yield, &codec.encode(&value))
+        .map_err(ReturnError::Write)
+}
+
+/// Writes the return value, encoded by `encode`. It is framed, in the codec
+/// the caller asked for, if the caller advertised a protocol version we speak
+/// and bare JSON otherwise. A stream in progress is ended on its own
+/// connection.
+fn send<F>(socket_path: &str, encode: F) -> Result<(), ReturnError>
+where F: Fn(Codec) -> Vec<u8> {
+    // Encode before connecting: reading a streamed argument in the value
+    // takes a connection of its own, which the caller only accepts once it
+    // is done with the one before.
+    let version = frame::negotiated_version();
+    let codec = version.map_or(Codec::Json, |_| Codec::negotiated());
+    let payload = encode(codec);
+    let streaming = STREAM.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let mut stream = match streaming {
+        Some(stream) => stream,
+        None => LocalSocketStream::connect(socket_path).map_err(ReturnError::Connect)?,
+    };
+    match version {
+        Some(version) => {
+            frame::write_frame(&mut stream, version, codec.content_type(), FrameKind::Return, &payload)
+                .map_err(ReturnError::Write)?;
+        }
+        None => {
+            stream.write_all(&payload).map_err(ReturnError::Write)?;
+            stream.flush().map_err(ReturnError::Write)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_and_exit(value: &Value) -> ! {
+    match value {
+        Value::Failure(msg) => {
+            eprintln!("{}", msg);
+            std::process::exit(1);
+        }
+        _ => {
+            println!("{}", value);
+            std::process::exit(0);
+        }
+    }
+}
+
+/// Arguments that do not fit the parameters of a function.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArgumentError {
+    /// The number of arguments differs from the number of parameters.
+    Arity { expected: usize, found: usize },
+    /// An argument could not be converted to the type of its parameter.
+    /// `position` counts from 1.
+    Type {
+        position: usize,
+        name: String,
+        expected: String,
+        reason: String,
+    },
+    /// `CAAT_ARGS` is set but does not hold a list of tagged values.
+    Malformed(String),
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgumentError::Arity { expected, found } => {
+                write!(f, "expected {} argument{}, got {}", expected, if *expected == 1 { "" } else { "s" }, found)
+            }
+            ArgumentError::Type { position, name, expected, reason } => {
+                write!(f, "argument {} (`{}`) must be {}: {}", position, name, expected, reason)
+            }
+            ArgumentError::Malformed(reason) => write!(f, "malformed {}: {}", ARGS_VAR, reason),
+        }
+    }
+}
+
+impl Error for ArgumentError {}
+
+/// The arguments of a callee, handed out one parameter at a time.
+pub struct Params {
+    values: std::vec::IntoIter<Value>,
+    position: usize,
+    from_shell: bool,
+}
+
+impl Params {
+    /// Collects the arguments of this process and checks there are `arity` of
+    /// them.
+    ///
+    /// They come from `CAAT_ARGS` when the program was started by a caller,
+    /// and from the command line, without the program name, otherwise. A
+    /// `CAAT_ARGS` that cannot be read is an error rather than a reason to
+    /// look at the command line.
+    pub fn collect(arity: usize) -> Result<Params, ArgumentError> {
+        let (values, from_shell) = collect_args()?;
+        if values.len() != arity {
+            return Err(ArgumentError::Arity { expected: arity, found: values.len() });
+        }
+        Ok(Params { values: values.into_iter(), position: 0, from_shell })
+    }
+
+    /// Converts the next argument to the type of the parameter `name`.
+    ///
+    /// Arguments typed on a shell are all strings, so one that does not
+    /// convert as it is gets a second try read as plain JSON: `42` becomes an
+    /// integer, `[1, 2]` a list and so on.
+    pub fn next<T>(&mut self, name: &'static str) -> Result<T, ArgumentError>
+    where
+        T: TryFrom<Value>,
+        T::Error: fmt::Display,
+    {
+        self.position += 1;
+        let value = self.values.next()
+            .ok_or(ArgumentError::Arity { expected: self.position, found: self.position - 1 })?;
+        let type_error = |reason: String| ArgumentError::Type {
+            position: self.position,
+            name: name.to_string(),
+            expected: std::any::type_name::<T>().to_string(),
+            reason,
+        };
+        match (self.from_shell, value) {
+            (true, Value::String(s)) => match T::try_from(Value::String(s.clone())) {
+                Ok(arg) => Ok(arg),
+                Err(e) => match json::parse(&s) {
+                    Ok(json) => T::try_from(from_plain_json(json)).map_err(|e| type_error(e.to_string())),
+                    Err(_) => Err(type_error(e.to_string())),
+                },
+            },
+            (_, value) => T::try_from(value).map_err(|e| type_error(e.to_string())),
+        }
+    }
+}
+
+/// The arguments of this process, and whether they were typed on a shell.
+fn collect_args() -> Result<(Vec<Value>, bool), ArgumentError> {
+    match caller_args() {
+        Some(args) => Ok((args?, false)),
+        None => Ok((std::env::args_os().skip(1).map(Value::from).collect(), true)),
+    }
+}
+
+/// The arguments a caller passed in `CAAT_ARGS`, or `None` when it is not
+/// set.
+pub(crate) fn caller_args() -> Option<Result<Vec<Value>, ArgumentError>> {
+    let args = std::env::var_os(ARGS_VAR)?;
+    let parse = || {
+        let args = args.to_str().ok_or_else(|| ArgumentError::Malformed("not valid UTF-8".to_string()))?;
+        let json = json::parse(args).map_err(|e| ArgumentError::Malformed(e.to_string()))?;
+        if !json.is_array() {
+            return Err(ArgumentError::Malformed("expected a list".to_string()));
+        }
+        json.members().enumerate()
+            .map(|(i, member)| Value::from_json_value(member)
+                .ok_or_else(|| ArgumentError::Malformed(format!("argument {} is not a tagged value", i + 1))))
+            .collect()
+    };
+    Some(parse())
+}
+
+/// Converts untagged JSON, as typed on a command line, into a `Value`.
+fn from_plain_json(json: JsonValue) -> Value {
+    match json {
+        JsonValue::Null => Value::Null,
+        JsonValue::Short(s) => Value::from(s.as_str()),
+        JsonValue::String(s) => Value::String(s),
+        JsonValue::Boolean(b) => Value::Boolean(b),
+        JsonValue::Number(n) => {
+            let json = JsonValue::Number(n);
+            match json.as_i64() {
+                Some(i) => Value::Integer(i),
+                None => Value::Float(json.as_f64().unwrap_or(f64::NAN)),
+            }
+        }
+        JsonValue::Array(array) => Value::from(array.into_iter().map(from_plain_json).collect::<Vec<_>>()),
+        JsonValue::Object(object) => {
+            let map = object.iter()
+                .map(|(key, value)| (key.to_string(), from_plain_json(value.clone())))
+                .collect::<HashMap<_, _>>();
+            Value::Map(map, None)
+        }
+    }
+}
+
+/// Runs the body of a `#[caat_rust::main]` function and returns its result to
+/// the caller, or prints it when run from a shell.
+///
+/// `body` gets the collected arguments and returns the value to hand back;
+/// argument errors are handed back as a `Value::Failure`. When the caller only
+/// asked for the signature, `body` is not run and the signature is returned
+/// instead.
+pub fn run<S, F>(signature: S, body: F) -> !
+where
+    S: FnOnce() -> Signature,
+    F: FnOnce(&mut Params) -> Result<Value, ArgumentError>,
+{
+    let signature = signature();
+    let value = if introspecting() {
+        signature.to_value()
+    } else {
+        Params::collect(signature.params.len())
+            .and_then(|mut params| body(&mut params))
+            .unwrap_or_else(|e| Value::Failure(e.to_string()))
+    };
+    answer(value)
+}
+
+/// Whether this process was started by `ForeignFunction::signature`, which
+/// takes both the variable and the argument, so neither can trigger it alone.
+fn introspecting() -> bool {
+    std::env::var_os(INTROSPECT_VAR).is_some() && std::env::args_os().last().is_some_and(|arg| arg == INTROSPECT_ARG)
+}
+
+/// Returns `value` to the caller and exits, printing the error and exiting
+/// with code 1 if it cannot be delivered.
+pub(crate) fn answer(value: Value) -> ! {
+    match return_value(value) {
+        Ok(never) => match never {},
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Answers calls with `handler` until the caller is done, then exits.
+///
+/// When started by `ForeignFunction::spawn_worker` the process stays alive and
+/// runs `handler` once for every call sent to it, so state kept by the handler
+/// carries over from one call to the next. The loop ends when the caller asks
+/// the worker to shut down or goes away. Started any other way, `handler` runs
+/// once with the arguments of this process and its result is returned as by
+/// `return_value`.
+pub fn serve_loop<F>(mut handler: F) -> !
+where F: FnMut(&[Value]) -> Value {
+    let worker = std::env::var_os(WORKER_VAR).is_some();
+    let version = frame::negotiated_version().filter(|version| *version >= frame::WORKER_VERSION);
+    let result = match (worker, std::env::var(SOCKET_VAR), version) {
+        (true, Ok(socket_path), Some(version)) => worker_loop(&socket_path, version, &mut handler),
+        _ => {
+            let value = collect_args().map(|(args, _)| handler(&args)).unwrap_or_else(|e| Value::Failure(e.to_string()));
+            return_value(value).map(|never| match never {})
+        }
+    };
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Announces the worker on its socket, then answers `Call` frames on that
+/// connection until a `Shutdown` frame or the end of the connection.
+fn worker_loop<F>(socket_path: &str, version: u8, handler: &mut F) -> Result<(), ReturnError>
+where F: FnMut(&[Value]) -> Value {
+    let mut stream = LocalSocketStream::connect(socket_path)
+        .map_err(ReturnError::Connect)?;
+    // The caller sends calls in the codec the worker announces itself in.
+    let codec = Codec::negotiated();
+    frame::write_frame(&mut stream, version, codec.content_type(), FrameKind::Ready, &[])
+        .map_err(ReturnError::Write)?;
+    loop {
+        let call = match frame::read_message(&mut stream) {
+            Ok(Message::Frame(call)) if call.kind == FrameKind::Call => call,
+            _ => return Ok(()),
+        };
+        let args = Codec::from_content_type(call.content_type).ok()
+            .and_then(|codec| codec.decode_args(&call.payload));
+        let value = match args {
+            Some(args) => handler(&args),
+            None => Value::Failure("malformed call".to_string()),
+        };
+        frame::write_frame(&mut stream, version, codec.content_type(), FrameKind::Return, &codec.encode(&value))
+            .map_err(ReturnError::Write)?;
+    }
+}
@@ -0,0 +1,79 @@
+//! Cancelling calls from another thread.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+type Waker = Box<dyn Fn() + Send>;
+
+/// A handle that aborts the calls it was passed to.
+///
+/// Clones share the same state, so one clone can be handed to a call through
+/// `CallOptions::cancellation` while another is kept to cancel it. Once
+/// cancelled a token stays cancelled, and any call started with it fails with
+/// `CaatError::Cancelled` right away.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    next_id: AtomicU64,
+    wakers: Mutex<Vec<(u64, Waker)>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels every call using this token. The callees are terminated and
+    /// their sockets removed.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        let wakers = std::mem::take(&mut *self.inner.wakers.lock().unwrap_or_else(|e| e.into_inner()));
+        for (_, waker) in wakers {
+            waker();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Runs `waker` when the token is cancelled, or right away if it already
+    /// is. The waker is forgotten when the returned guard is dropped.
+    pub(crate) fn register<F: Fn() + Send + 'static>(&self, waker: F) -> Registration {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut wakers = self.inner.wakers.lock().unwrap_or_else(|e| e.into_inner());
+            if !self.is_cancelled() {
+                wakers.push((id, Box::new(waker)));
+                return Registration { token: self.clone(), id };
+            }
+        }
+        waker();
+        Registration { token: self.clone(), id }
+    }
+}
+
+impl std::fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+pub(crate) struct Registration {
+    token: CancellationToken,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut wakers = self.token.inner.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        wakers.retain(|(id, _)| *id != self.id);
+    }
+}
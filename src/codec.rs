@@ -0,0 +1,477 @@
+//! Encodings of values on the wire.
+//!
+//! Values travel as the tagged JSON form of `Value::to_json` unless the caller
+//! asks for another codec with `ForeignFunction::with_codec`. The caller names
+//! it in `CAAT_CODEC`, and a callee built with that codec answers in it, while
+//! any other callee keeps answering in JSON. Each frame says which codec its
+//! payload is in, so the caller reads either. The arguments in `CAAT_ARGS` and
+//! the messages of callbacks and streamed arguments stay JSON.
+//!
+//! The binary codecs, MessagePack with the `msgpack` feature and CBOR with the
+//! `cbor` feature, write integers, floats, strings, bytes, booleans, null and
+//! lists as their native types. Everything else is a map with a `type` and a
+//! `value` entry, as in JSON, and big integers and decimals are strings there
+//! too.
+
+use std::collections::HashMap;
+use json::JsonValue;
+use crate::callback::Callbacks;
+use crate::frame::{ContentType, Frame};
+use crate::{CaatError, Value};
+
+pub(crate) const CODEC_VAR: &str = "CAAT_CODEC";
+
+/// How values are encoded, see the module documentation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// The tagged JSON form, which every callee understands.
+    #[default]
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Codec {
+    /// The codecs this build of the library supports.
+    pub const AVAILABLE: &'static [Codec] = &[
+        Codec::Json,
+        #[cfg(feature = "msgpack")]
+        Codec::MessagePack,
+        #[cfg(feature = "cbor")]
+        Codec::Cbor,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => "msgpack",
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => "cbor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Codec> {
+        Codec::AVAILABLE.iter().copied().find(|codec| codec.name() == name)
+    }
+
+    /// Encodes a value. Streams are read to the end and written as lists,
+    /// which uses them up for every clone, see `Value::to_json`.
+    pub fn encode(&self, value: &Value) -> Vec<u8> {
+        self.encode_with(value, None)
+    }
+
+    /// Decodes a value. Callbacks and streams in it are resolved against the
+    /// caller of this process, as for arguments.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Value, CaatError> {
+        self.decode_with(bytes, None)
+    }
+
+    /// Encodes a value, registering functions and streams in `callbacks`
+    /// like `Value::encode`.
+    pub(crate) fn encode_with(&self, value: &Value, callbacks: Option<&Callbacks>) -> Vec<u8> {
+        match self {
+            Codec::Json => value.encode(callbacks).dump().into_bytes(),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => msgpack::write(Wire::encode(value, callbacks)),
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => cbor::write(Wire::encode(value, callbacks)),
+        }
+    }
+
+    pub(crate) fn decode_with(&self, bytes: &[u8], callbacks: Option<&Callbacks>) -> Result<Value, CaatError> {
+        let not_tagged = || CaatError::DecodeError("not a tagged CAAT value".to_string());
+        match self {
+            Codec::Json => Value::decode(&Codec::parse_json(bytes)?, callbacks).ok_or_else(not_tagged),
+            #[cfg(any(feature = "msgpack", feature = "cbor"))]
+            _ => self.read(bytes)?.decode(callbacks).ok_or_else(not_tagged),
+        }
+    }
+
+    /// Encodes the arguments of a call. In JSON they are a bare list of
+    /// tagged values, as in `CAAT_ARGS`, and otherwise a list value.
+    pub(crate) fn encode_args<I>(&self, args: I, callbacks: &Callbacks) -> Vec<u8>
+    where I: Iterator<Item = Value> {
+        match self {
+            Codec::Json => JsonValue::Array(args.map(|arg| arg.encode(Some(callbacks))).collect()).dump().into_bytes(),
+            #[cfg(any(feature = "msgpack", feature = "cbor"))]
+            _ => self.encode_with(&Value::List(args.collect()), Some(callbacks)),
+        }
+    }
+
+    /// Decodes the arguments of a call written by `encode_args`.
+    pub(crate) fn decode_args(&self, bytes: &[u8]) -> Option<Vec<Value>> {
+        match self {
+            Codec::Json => Codec::parse_json(bytes).ok()?.members().map(Value::from_json_value).collect(),
+            #[cfg(any(feature = "msgpack", feature = "cbor"))]
+            _ => match self.decode(bytes).ok()? {
+                Value::List(args) => Some(args.into_vec()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Encodes a failure with structured `data` attached, see
+    /// `callee::return_failure`.
+    pub(crate) fn encode_failure(&self, failure: &Value, data: &Value) -> Vec<u8> {
+        match self {
+            Codec::Json => {
+                let mut json = failure.to_json_value();
+                json["data"] = data.to_json_value();
+                json.dump().into_bytes()
+            }
+            #[cfg(any(feature = "msgpack", feature = "cbor"))]
+            _ => {
+                self.write(Wire::encode(failure, None).with("data", Wire::encode(data, None)))
+            }
+        }
+    }
+
+    /// Decodes what a callee returned. Callbacks it returns are resolved
+    /// against `callbacks`, giving back the functions passed in.
+    ///
+    /// A returned `Value::Failure` becomes a `CaatError::RemoteFailure`, which
+    /// also picks up the optional `data` field the callee may attach.
+    pub(crate) fn decode_response(&self, bytes: &[u8], callbacks: Option<&Callbacks>) -> Result<Value, CaatError> {
+        let (value, data) = match self {
+            Codec::Json => {
+                let json = Codec::parse_json(bytes)?;
+                let value = Value::decode(&json, callbacks);
+                let data = match &json["data"] {
+                    JsonValue::Null => None,
+                    data => Some(Value::decode(data, callbacks)),
+                };
+                (value, data)
+            }
+            #[cfg(any(feature = "msgpack", feature = "cbor"))]
+            _ => {
+                let mut wire = self.read(bytes)?;
+                let data = wire.take("data").map(|data| data.decode(callbacks));
+                (wire.decode(callbacks), data)
+            }
+        };
+        match value.ok_or_else(|| CaatError::DecodeError("not a tagged CAAT value".to_string()))? {
+            Value::Failure(message) => {
+                let data = data.map(|data| {
+                    data.ok_or_else(|| CaatError::DecodeError("failure data is not a tagged CAAT value".to_string()))
+                });
+                Err(CaatError::RemoteFailure { message, data: data.transpose()? })
+            }
+            value => Ok(value),
+        }
+    }
+
+    pub(crate) fn content_type(&self) -> ContentType {
+        match self {
+            Codec::Json => ContentType::Json,
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => ContentType::MessagePack,
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => ContentType::Cbor,
+        }
+    }
+
+    pub(crate) fn from_content_type(content_type: ContentType) -> Result<Codec, CaatError> {
+        Codec::AVAILABLE.iter().copied()
+            .find(|codec| codec.content_type() == content_type)
+            .ok_or_else(|| CaatError::DecodeError(format!("{:?} payloads are not supported by this build", content_type)))
+    }
+
+    /// The codec to answer the caller of this process in.
+    pub(crate) fn negotiated() -> Codec {
+        std::env::var(CODEC_VAR).ok()
+            .and_then(|name| Codec::from_name(&name))
+            .unwrap_or_default()
+    }
+
+    fn parse_json(bytes: &[u8]) -> Result<JsonValue, CaatError> {
+        let string = std::str::from_utf8(bytes)
+            .map_err(|e| CaatError::DecodeError(e.to_string()))?;
+        json::parse(string).map_err(|e| CaatError::DecodeError(e.to_string()))
+    }
+
+    #[cfg(any(feature = "msgpack", feature = "cbor"))]
+    fn write(&self, wire: Wire) -> Vec<u8> {
+        match self {
+            Codec::Json => unreachable!("JSON is not written through `Wire`"),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => msgpack::write(wire),
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => cbor::write(wire),
+        }
+    }
+
+    #[cfg(any(feature = "msgpack", feature = "cbor"))]
+    fn read(&self, bytes: &[u8]) -> Result<Wire, CaatError> {
+        let wire = match self {
+            Codec::Json => unreachable!("JSON is not read through `Wire`"),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => msgpack::read(bytes)?,
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => cbor::read(bytes)?,
+        };
+        wire.ok_or_else(|| CaatError::DecodeError(format!("the payload has types {} cannot carry", self.name())))
+    }
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Decodes the payload of a frame in whatever codec it says it is in.
+pub(crate) fn decode_frame(frame: &Frame, callbacks: Option<&Callbacks>) -> Result<Value, CaatError> {
+    Codec::from_content_type(frame.content_type)?.decode_response(&frame.payload, callbacks)
+}
+
+/// Checks that nothing is left of a payload after its value.
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+fn expect_end(rest: &[u8]) -> Result<(), CaatError> {
+    match rest.len() {
+        0 => Ok(()),
+        n => Err(CaatError::DecodeError(format!("{} bytes left after the value", n))),
+    }
+}
+
+/// The data model shared by the binary codecs.
+#[cfg_attr(not(any(feature = "msgpack", feature = "cbor")), allow(dead_code))]
+enum Wire {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Boolean(bool),
+    Null,
+    List(Vec<Wire>),
+    Map(Vec<(String, Wire)>),
+}
+
+#[cfg_attr(not(any(feature = "msgpack", feature = "cbor")), allow(dead_code))]
+impl Wire {
+    fn tagged(the_type: &str, value: Wire) -> Wire {
+        Wire::Map(vec![("type".to_string(), Wire::String(the_type.to_string())), ("value".to_string(), value)])
+    }
+
+    /// Adds an entry to a map.
+    fn with(mut self, key: &str, value: Wire) -> Wire {
+        if let Wire::Map(entries) = &mut self {
+            entries.push((key.to_string(), value));
+        }
+        self
+    }
+
+    /// Removes an entry from a map.
+    fn take(&mut self, key: &str) -> Option<Wire> {
+        match self {
+            Wire::Map(entries) => {
+                let index = entries.iter().position(|(k, _)| k == key)?;
+                Some(entries.remove(index).1)
+            }
+            _ => None,
+        }
+    }
+
+    fn encode(value: &Value, callbacks: Option<&Callbacks>) -> Wire {
+        match value {
+            Value::Integer(i) => Wire::Integer(*i),
+            Value::BigInt(i) => Wire::tagged("BigInt", Wire::String(i.to_string())),
+            Value::Decimal(d) => Wire::tagged("Decimal", Wire::String(d.to_string())),
+            Value::Timestamp(_) => Wire::tagged("Timestamp", Wire::String(value.to_string())),
+            Value::Duration(_) => Wire::tagged("Duration", Wire::String(value.to_string())),
+            Value::String(s) => Wire::String(s.clone()),
+            Value::Bytes(b) => Wire::Bytes(b.clone()),
+            Value::Float(f) => Wire::Float(*f),
+            Value::Map(d, format) => {
+                // Sorted like the JSON form, so equal values encode the same.
+                let mut keys = d.keys().collect::<Vec<&String>>();
+                keys.sort();
+                let entries = keys.into_iter()
+                    .map(|key| (key.clone(), Wire::encode(&d[key], callbacks)))
+                    .collect();
+                let map = Wire::tagged("Map", Wire::Map(entries));
+                match format {
+                    Some(format) => map.with("format", Wire::String(format.clone())),
+                    None => map,
+                }
+            }
+            Value::List(l) => Wire::List(l.iter().map(|value| Wire::encode(value, callbacks)).collect()),
+            Value::Boolean(b) => Wire::Boolean(*b),
+            Value::Null => Wire::Null,
+            Value::CAATFunction(f) => {
+                let function = Wire::tagged("CAAT", Wire::String(f.to_string()));
+                match (f.as_foreign(), callbacks, f.callback_id()) {
+                    (None, Some(callbacks), _) => function.with("callback", Wire::Integer(callbacks.register(f.clone()) as i64)),
+                    (None, None, Some(id)) => function.with("callback", Wire::Integer(id as i64)),
+                    _ => function,
+                }
+            }
+            Value::Failure(msg) => Wire::tagged("Failure", Wire::String(msg.clone())),
+            Value::Stream(stream) => match callbacks {
+                Some(callbacks) => Wire::tagged("Stream", Wire::Integer(callbacks.register_stream(stream.clone()) as i64)),
+                None => Wire::List(stream.clone().map(|value| Wire::encode(&value, None)).collect()),
+            },
+        }
+    }
+
+    fn decode(self, callbacks: Option<&Callbacks>) -> Option<Value> {
+        match self {
+            Wire::Integer(i) => Some(Value::Integer(i)),
+            Wire::Float(f) => Some(Value::Float(f)),
+            Wire::String(s) => Some(Value::String(s)),
+            Wire::Bytes(b) => Some(Value::Bytes(b)),
+            Wire::Boolean(b) => Some(Value::Boolean(b)),
+            Wire::Null => Some(Value::Null),
+            Wire::List(l) => {
+                let list = l.into_iter().map(|wire| wire.decode(callbacks)).collect::<Option<Vec<Value>>>()?;
+                Some(Value::List(list.into_boxed_slice()))
+            }
+            mut map @ Wire::Map(_) => {
+                let the_type = match map.take("type")? {
+                    Wire::String(the_type) => the_type,
+                    _ => return None,
+                };
+                let id = |wire: Wire| match wire {
+                    Wire::Integer(i) => usize::try_from(i).ok(),
+                    _ => None,
+                };
+                match (the_type.as_str(), map.take("value")?) {
+                    ("Map", Wire::Map(entries)) => {
+                        let mut d = HashMap::with_capacity(entries.len());
+                        for (key, value) in entries {
+                            d.insert(key, value.decode(callbacks)?);
+                        }
+                        let format = match map.take("format") {
+                            Some(Wire::String(format)) => Some(format),
+                            _ => None,
+                        };
+                        Some(Value::Map(d, format))
+                    }
+                    ("CAAT", Wire::String(command)) => {
+                        let callback = map.take("callback").and_then(id);
+                        Some(Value::decode_function(&command, callback, callbacks))
+                    }
+                    ("BigInt", Wire::String(i)) => i.parse().ok().map(Value::big_int),
+                    ("Decimal", Wire::String(d)) => d.parse().ok().map(Value::Decimal),
+                    ("Timestamp", Wire::String(t)) => t.parse().ok().map(Value::Timestamp),
+                    ("Duration", Wire::String(d)) => crate::timestamp::parse_duration(&d).map(Value::Duration),
+                    ("Failure", Wire::String(msg)) => Some(Value::Failure(msg)),
+                    ("Stream", value) => Value::decode_stream(id(value)?, callbacks),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+mod msgpack {
+    use rmpv::Value as Msgpack;
+    use super::Wire;
+    use crate::CaatError;
+
+    pub(super) fn write(wire: Wire) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, &to_msgpack(wire)).expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Reads a payload, or `None` if it holds values `Wire` has no place for,
+    /// such as extension types or integers beyond `i64`.
+    pub(super) fn read(mut bytes: &[u8]) -> Result<Option<Wire>, CaatError> {
+        // rmpv reads the reserved marker as nil, so it is refused up front.
+        if bytes.first() == Some(&0xc1) {
+            return Err(CaatError::DecodeError("reserved MessagePack marker".to_string()));
+        }
+        let value = rmpv::decode::read_value(&mut bytes).map_err(|e| CaatError::DecodeError(e.to_string()))?;
+        super::expect_end(bytes)?;
+        Ok(from_msgpack(value))
+    }
+
+    fn to_msgpack(wire: Wire) -> Msgpack {
+        match wire {
+            Wire::Integer(i) => Msgpack::from(i),
+            Wire::Float(f) => Msgpack::F64(f),
+            Wire::String(s) => Msgpack::from(s),
+            Wire::Bytes(b) => Msgpack::Binary(b),
+            Wire::Boolean(b) => Msgpack::Boolean(b),
+            Wire::Null => Msgpack::Nil,
+            Wire::List(l) => Msgpack::Array(l.into_iter().map(to_msgpack).collect()),
+            Wire::Map(m) => Msgpack::Map(m.into_iter().map(|(key, value)| (Msgpack::from(key), to_msgpack(value))).collect()),
+        }
+    }
+
+    fn from_msgpack(value: Msgpack) -> Option<Wire> {
+        match value {
+            Msgpack::Integer(i) => i.as_i64().map(Wire::Integer),
+            Msgpack::F32(f) => Some(Wire::Float(f.into())),
+            Msgpack::F64(f) => Some(Wire::Float(f)),
+            Msgpack::String(s) => s.into_str().map(Wire::String),
+            Msgpack::Binary(b) => Some(Wire::Bytes(b)),
+            Msgpack::Boolean(b) => Some(Wire::Boolean(b)),
+            Msgpack::Nil => Some(Wire::Null),
+            Msgpack::Array(l) => l.into_iter().map(from_msgpack).collect::<Option<_>>().map(Wire::List),
+            Msgpack::Map(m) => m.into_iter()
+                .map(|(key, value)| Some((key.as_str()?.to_string(), from_msgpack(value)?)))
+                .collect::<Option<_>>()
+                .map(Wire::Map),
+            Msgpack::Ext(..) => None,
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use ciborium::Value as Cbor;
+    use super::Wire;
+    use crate::CaatError;
+
+    pub(super) fn write(wire: Wire) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&to_cbor(wire), &mut bytes).expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Reads a payload, or `None` if it holds values `Wire` has no place for,
+    /// such as tags or integers beyond `i64`.
+    pub(super) fn read(mut bytes: &[u8]) -> Result<Option<Wire>, CaatError> {
+        let value = ciborium::from_reader::<Cbor, _>(&mut bytes).map_err(|e| CaatError::DecodeError(e.to_string()))?;
+        super::expect_end(bytes)?;
+        Ok(from_cbor(value))
+    }
+
+    fn to_cbor(wire: Wire) -> Cbor {
+        match wire {
+            Wire::Integer(i) => Cbor::Integer(i.into()),
+            Wire::Float(f) => Cbor::Float(f),
+            Wire::String(s) => Cbor::Text(s),
+            Wire::Bytes(b) => Cbor::Bytes(b),
+            Wire::Boolean(b) => Cbor::Bool(b),
+            Wire::Null => Cbor::Null,
+            Wire::List(l) => Cbor::Array(l.into_iter().map(to_cbor).collect()),
+            Wire::Map(m) => Cbor::Map(m.into_iter().map(|(key, value)| (Cbor::Text(key), to_cbor(value))).collect()),
+        }
+    }
+
+    fn from_cbor(value: Cbor) -> Option<Wire> {
+        match value {
+            Cbor::Integer(i) => i64::try_from(i).ok().map(Wire::Integer),
+            Cbor::Float(f) => Some(Wire::Float(f)),
+            Cbor::Text(s) => Some(Wire::String(s)),
+            Cbor::Bytes(b) => Some(Wire::Bytes(b)),
+            Cbor::Bool(b) => Some(Wire::Boolean(b)),
+            Cbor::Null => Some(Wire::Null),
+            Cbor::Array(l) => l.into_iter().map(from_cbor).collect::<Option<_>>().map(Wire::List),
+            Cbor::Map(m) => m.into_iter()
+                .map(|(key, value)| match key {
+                    Cbor::Text(key) => Some((key, from_cbor(value)?)),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .map(Wire::Map),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Deserializing Rust types from `Value`, enabled by the `serde` feature.
+//!
+//! This is the inverse of `ser`: maps fill structs and maps, lists fill
+//! sequences and tuples, `Value::Null` is `None` or `()`, and enums are read
+//! from a variant name or a single entry map. A `Value::Stream` is read like a
+//! list, a `Value::Bytes` is handed to the visitor as a byte buffer and a
+//! `Value::BigInt` fills any integer type it fits in. A `Value::Failure` only
+//! deserializes into a `Value`, so decoding the result of a failed call into
+//! any other type is an error.
+
+use std::collections::{hash_map, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use serde::de::{self, Deserialize, DeserializeOwned, IntoDeserializer, Visitor};
+use crate::error::SerdeError;
+use crate::{ForeignFunction, Value};
+
+/// Converts a `Value` into any deserializable type.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, SerdeError> {
+    T::deserialize(value)
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "any value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Integer(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut list: Vec<Value> = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            list.push(value);
+        }
+        Ok(Value::from(list))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut d = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry()? {
+            d.insert(key, value);
+        }
+        Ok(Value::Map(d, None))
+    }
+
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        use de::VariantAccess;
+        let (variant, content): (String, _) = data.variant()?;
+        let value: Value = content.newtype_variant()?;
+        match (variant.as_str(), value) {
+            ("Failure", Value::String(msg)) => Ok(Value::Failure(msg)),
+            ("CAAT", Value::String(command)) => Ok(Value::CAATFunction(Arc::new(ForeignFunction::new(&command)))),
+            ("BigInt", Value::String(i)) => i.parse().map(Value::big_int).map_err(de::Error::custom),
+            ("Decimal", Value::String(d)) => d.parse().map(Value::Decimal).map_err(de::Error::custom),
+            ("Timestamp", Value::String(t)) => t.parse().map(Value::Timestamp).map_err(de::Error::custom),
+            ("Duration", Value::String(d)) => crate::timestamp::parse_duration(&d).map(Value::Duration)
+                .ok_or_else(|| de::Error::custom("not an ISO 8601 duration")),
+            (_, value) => {
+                let mut map = HashMap::new();
+                map.insert(variant, value);
+                Ok(Value::Map(map, None))
+            }
+        }
+    }
+}
+
+impl Value {
+    fn invalid_type<E: de::Error>(&self, expected: &dyn de::Expected) -> E {
+        let unexpected = match self {
+            Value::Integer(i) => de::Unexpected::Signed(*i),
+            Value::BigInt(_) => de::Unexpected::Other("big integer"),
+            Value::Decimal(_) => de::Unexpected::Other("decimal"),
+            Value::Timestamp(_) => de::Unexpected::Other("timestamp"),
+            Value::Duration(_) => de::Unexpected::Other("duration"),
+            Value::String(s) => de::Unexpected::Str(s),
+            Value::Bytes(b) => de::Unexpected::Bytes(b),
+            Value::Float(f) => de::Unexpected::Float(*f),
+            Value::Map(..) => de::Unexpected::Map,
+            Value::List(..) | Value::Stream(..) => de::Unexpected::Seq,
+            Value::Boolean(b) => de::Unexpected::Bool(*b),
+            Value::Null => de::Unexpected::Unit,
+            Value::CAATFunction(..) => de::Unexpected::Other("CAAT function"),
+            Value::Failure(msg) => return E::custom(format!("call failed: {}", msg)),
+        };
+        E::invalid_type(unexpected, expected)
+    }
+}
+
+/// Forwards to `deserialize_any`, except that a `Value::Failure` is reported
+/// as the failure it is rather than as a type mismatch.
+macro_rules! forward_unless_failure {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+                if let Value::Failure(_) = self {
+                    return Err(self.invalid_type(&visitor));
+                }
+                self.deserialize_any(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Value::Integer(i) => visitor.visit_i64(i),
+            // Integers go to the narrowest visitor they fit, so that fields
+            // of the wider integer types can take them.
+            Value::BigInt(i) => match (i64::try_from(&i), u64::try_from(&i), i128::try_from(&i), u128::try_from(&i)) {
+                (Ok(i), ..) => visitor.visit_i64(i),
+                (_, Ok(u), ..) => visitor.visit_u64(u),
+                (_, _, Ok(i), _) => visitor.visit_i128(i),
+                (.., Ok(u)) => visitor.visit_u128(u),
+                _ => visitor.visit_enum(EnumDeserializer {
+                    variant: "BigInt".to_string(),
+                    value: Some(Value::String(i.to_string())),
+                }),
+            },
+            Value::Decimal(d) => visitor.visit_enum(EnumDeserializer {
+                variant: "Decimal".to_string(),
+                value: Some(Value::String(d.to_string())),
+            }),
+            Value::Timestamp(_) => visitor.visit_enum(EnumDeserializer {
+                variant: "Timestamp".to_string(),
+                value: Some(Value::String(self.to_string())),
+            }),
+            Value::Duration(_) => visitor.visit_enum(EnumDeserializer {
+                variant: "Duration".to_string(),
+                value: Some(Value::String(self.to_string())),
+            }),
+            Value::String(s) => visitor.visit_string(s),
+            Value::Bytes(b) => visitor.visit_byte_buf(b),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Map(d, _) => visitor.visit_map(MapDeserializer::new(d)),
+            Value::List(l) => visitor.visit_seq(SeqDeserializer::new(l.into_vec())),
+            Value::Stream(s) => visitor.visit_seq(SeqDeserializer::new(s.collect())),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Null => visitor.visit_unit(),
+            Value::CAATFunction(f) => visitor.visit_enum(EnumDeserializer {
+                variant: "CAAT".to_string(),
+                value: Some(Value::String(f.to_string())),
+            }),
+            Value::Failure(msg) => visitor.visit_enum(EnumDeserializer {
+                variant: "Failure".to_string(),
+                value: Some(Value::String(msg)),
+            }),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Value::Null => visitor.visit_none(),
+            Value::Failure(_) => Err(self.invalid_type(&visitor)),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Value::String(variant) => visitor.visit_enum(EnumDeserializer { variant, value: None }),
+            Value::Map(d, _) if d.len() == 1 => {
+                let (variant, value) = d.into_iter().next().expect("map has one entry");
+                visitor.visit_enum(EnumDeserializer { variant, value: Some(value) })
+            }
+            value => Err(value.invalid_type(&"a variant name or a map with a single entry")),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    forward_unless_failure! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_i128 deserialize_u8 deserialize_u16
+        deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_seq
+        deserialize_map deserialize_identifier
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_map(visitor)
+    }
+}
+
+impl IntoDeserializer<'_, SerdeError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+struct SeqDeserializer {
+    iter: std::vec::IntoIter<Value>,
+}
+
+impl SeqDeserializer {
+    fn new(list: Vec<Value>) -> SeqDeserializer {
+        SeqDeserializer { iter: list.into_iter() }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDeserializer {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        match self.iter.next() {
+            Some(value) => seed.deserialize(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapDeserializer {
+    iter: hash_map::IntoIter<String, Value>,
+    value: Option<Value>,
+}
+
+impl MapDeserializer {
+    fn new(map: HashMap<String, Value>) -> MapDeserializer {
+        MapDeserializer { iter: map.into_iter(), value: None }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapDeserializer {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(SerdeError::new("map value requested before its key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Option<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = SerdeError;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, VariantDeserializer), SerdeError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer {
+    value: Option<Value>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.value {
+            None | Some(Value::Null) => Ok(()),
+            Some(value) => Err(value.invalid_type(&"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, SerdeError> {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"newtype variant")),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Some(Value::List(l)) => visitor.visit_seq(SeqDeserializer::new(l.into_vec())),
+            Some(value) => Err(value.invalid_type(&"tuple variant")),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"tuple variant")),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Some(Value::Map(d, _)) => visitor.visit_map(MapDeserializer::new(d)),
+            Some(value) => Err(value.invalid_type(&"struct variant")),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"struct variant")),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Errors raised while calling a foreign function.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use crate::callee::ArgumentError;
+use crate::Value;
+
+#[derive(Debug)]
+pub enum CaatError {
+    /// The command could not be started.
+    SpawnFailed(io::Error),
+    /// The socket for the return value could not be created.
+    SocketBind(io::Error),
+    /// Waiting for the callee to connect to the socket failed.
+    Accept(io::Error),
+    /// The callee did not return within the allowed time.
+    Timeout(Duration),
+    /// The call was aborted through its `CancellationToken`.
+    Cancelled,
+    /// The call would nest deeper than the given number of levels of calls
+    /// and callbacks, so it was not started.
+    DepthExceeded(u32),
+    /// A `WorkerPool` had no free worker and already had the given number of
+    /// calls waiting for one, so the call was turned away.
+    QueueFull(usize),
+    /// The callee exited unsuccessfully without returning a value.
+    ///
+    /// `code` is `None` when the process was killed by a signal, in which case
+    /// `signal` holds its number on Unix. `stderr` holds what the callee wrote
+    /// to its standard error.
+    NonZeroExit {
+        code: Option<i32>,
+        signal: Option<i32>,
+        stderr: String,
+    },
+    /// The arguments do not fit the signature of the command, so it was not
+    /// started.
+    ArgumentMismatch(ArgumentError),
+    /// The callee did not follow the protocol.
+    ProtocolError(String),
+    /// The callee answered with a protocol version this library cannot read.
+    IncompatibleVersion { ours: u8, theirs: u8 },
+    /// The return value could not be decoded.
+    DecodeError(String),
+    /// The callee returned a `Value::Failure`.
+    RemoteFailure {
+        message: String,
+        data: Option<Value>,
+    },
+}
+
+impl fmt::Display for CaatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaatError::SpawnFailed(e) => write!(f, "failed to spawn command: {}", e),
+            CaatError::SocketBind(e) => write!(f, "failed to bind socket: {}", e),
+            CaatError::Accept(e) => write!(f, "failed to accept connection: {}", e),
+            CaatError::Timeout(d) => write!(f, "call timed out after {:?}", d),
+            CaatError::Cancelled => write!(f, "call was cancelled"),
+            CaatError::DepthExceeded(limit) => write!(f, "calls are nested deeper than {} levels", limit),
+            CaatError::QueueFull(waiting) => write!(f, "worker pool queue is full with {} calls waiting", waiting),
+            CaatError::NonZeroExit { code, signal, stderr } => {
+                match (code, signal) {
+                    (Some(code), _) => write!(f, "command exited with code {}", code)?,
+                    (None, Some(signal)) => write!(f, "command was killed by signal {}", signal)?,
+                    (None, None) => write!(f, "command exited unsuccessfully")?,
+                }
+                let stderr = stderr.trim_end();
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                Ok(())
+            }
+            CaatError::ArgumentMismatch(e) => write!(f, "invalid arguments: {}", e),
+            CaatError::ProtocolError(msg) => write!(f, "protocol error: {}", msg),
+            CaatError::IncompatibleVersion { ours, theirs } => {
+                write!(f, "callee speaks protocol version {}, but only versions up to {} are supported", theirs, ours)
+            }
+            CaatError::DecodeError(msg) => write!(f, "failed to decode return value: {}", msg),
+            CaatError::RemoteFailure { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for CaatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CaatError::SpawnFailed(e) => Some(e),
+            CaatError::SocketBind(e) => Some(e),
+            CaatError::Accept(e) => Some(e),
+            CaatError::ArgumentMismatch(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<CaatError> for Value {
+    /// Collapses an error into a `Value::Failure`, the way `Caat::call`
+    /// reports errors.
+    fn from(error: CaatError) -> Self {
+        match error {
+            CaatError::RemoteFailure { message, .. } => Value::Failure(message),
+            error => Value::Failure(error.to_string()),
+        }
+    }
+}
+
+/// An error raised while converting between `Value` and a serde type.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerdeError(String);
+
+#[cfg(feature = "serde")]
+impl SerdeError {
+    pub(crate) fn new<T: Into<String>>(message: T) -> SerdeError {
+        SerdeError(message.into())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Error for SerdeError {}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError(msg.to_string())
+    }
+}
+
@@ -0,0 +1,125 @@
+//! Format tags for `Value::Map`.
+//!
+//! A map can carry a format name telling the receiver how its entries are laid
+//! out. Formats are looked up in a process wide registry which holds a
+//! validation hook for each known name. The built in formats are:
+//!
+//! * `"record"`: a set of named fields, every key must be non-empty.
+//! * `"table"`: every entry is a column, a `Value::List` of the same length.
+//! * `"csv-row"`: every entry is a scalar (string, integer, big integer,
+//!   decimal, float, boolean, timestamp, duration or null).
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, OnceLock, RwLock};
+use crate::Value;
+
+/// A validation hook for a map format.
+///
+/// It receives the entries of the map and returns a description of the problem
+/// when they do not fit the format.
+pub type FormatValidator = Arc<dyn Fn(&HashMap<String, Value>) -> Result<(), String> + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    /// The format name is not in the registry.
+    Unknown(String),
+    /// The map does not satisfy the validator of its format.
+    Invalid { format: String, reason: String },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Unknown(format) => write!(f, "unknown map format \"{}\"", format),
+            FormatError::Invalid { format, reason } => {
+                write!(f, "map is not a valid \"{}\": {}", format, reason)
+            }
+        }
+    }
+}
+
+impl Error for FormatError {}
+
+fn registry() -> &'static RwLock<HashMap<String, FormatValidator>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, FormatValidator>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut formats: HashMap<String, FormatValidator> = HashMap::new();
+        formats.insert("record".to_string(), Arc::new(validate_record));
+        formats.insert("table".to_string(), Arc::new(validate_table));
+        formats.insert("csv-row".to_string(), Arc::new(validate_csv_row));
+        RwLock::new(formats)
+    })
+}
+
+/// Registers a format, replacing the validator if the name is already known.
+pub fn register_format<F>(name: &str, validator: F)
+where F: Fn(&HashMap<String, Value>) -> Result<(), String> + Send + Sync + 'static {
+    let mut formats = registry().write().unwrap_or_else(|e| e.into_inner());
+    formats.insert(name.to_string(), Arc::new(validator));
+}
+
+pub fn is_known_format(name: &str) -> bool {
+    let formats = registry().read().unwrap_or_else(|e| e.into_inner());
+    formats.contains_key(name)
+}
+
+/// Returns the names of all registered formats in sorted order.
+pub fn known_formats() -> Vec<String> {
+    let formats = registry().read().unwrap_or_else(|e| e.into_inner());
+    let mut names = formats.keys().cloned().collect::<Vec<String>>();
+    names.sort();
+    names
+}
+
+/// Checks the entries of a map against the validator registered for `format`.
+pub fn validate(format: &str, map: &HashMap<String, Value>) -> Result<(), FormatError> {
+    let validator = {
+        let formats = registry().read().unwrap_or_else(|e| e.into_inner());
+        match formats.get(format) {
+            Some(validator) => validator.clone(),
+            None => return Err(FormatError::Unknown(format.to_string())),
+        }
+    };
+    validator(map).map_err(|reason| FormatError::Invalid {
+        format: format.to_string(),
+        reason,
+    })
+}
+
+fn validate_record(map: &HashMap<String, Value>) -> Result<(), String> {
+    if map.contains_key("") {
+        return Err("field names must not be empty".to_string());
+    }
+    Ok(())
+}
+
+fn validate_table(map: &HashMap<String, Value>) -> Result<(), String> {
+    let mut rows = None;
+    for (key, value) in map {
+        let len = match value {
+            Value::List(l) => l.len(),
+            _ => return Err(format!("column \"{}\" is not a list", key)),
+        };
+        match rows {
+            None => rows = Some(len),
+            Some(rows) if rows != len => {
+                return Err(format!("column \"{}\" has {} rows, expected {}", key, len, rows));
+            }
+            Some(_) => (),
+        }
+    }
+    Ok(())
+}
+
+fn validate_csv_row(map: &HashMap<String, Value>) -> Result<(), String> {
+    for (key, value) in map {
+        match value {
+            Value::String(_) | Value::Integer(_) | Value::BigInt(_) | Value::Decimal(_) | Value::Timestamp(_) | Value::Duration(_) | Value::Float(_)
+            | Value::Boolean(_) | Value::Null => (),
+            _ => return Err(format!("cell \"{}\" is not a scalar", key)),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! Framing of the messages sent over a call's socket.
+//!
+//! Every frame starts with a 12 byte header:
+//!
+//! | bytes | field                                   |
+//! |-------|-----------------------------------------|
+//! | 0..4  | magic, `b"CAAT"`                        |
+//! | 4     | protocol version                        |
+//! | 5     | content type of the payload             |
+//! | 6     | frame kind                              |
+//! | 7     | reserved, always 0                      |
+//! | 8..12 | payload length, big endian `u32`        |
+//!
+//! The caller advertises the newest version it understands in `CAAT_PROTOCOL`.
+//! A callee that finds no such variable, or only a version it cannot speak,
+//! writes a bare JSON value and closes the connection like older versions of
+//! this library did. Callers still accept that form so callees written
+//! against older libraries, or in other languages, keep working.
+//!
+//! Version 2 adds callback frames: a callee asks the caller to run a function
+//! it was passed by connecting to the socket and sending a `CallbackRequest`,
+//! which the caller answers with a `CallbackResponse` on the same connection.
+//!
+//! Version 3 adds persistent workers: a callee started in worker mode opens
+//! one connection, announces itself with `Ready`, then answers each `Call`
+//! with a `Return` until it gets `Shutdown` or the connection is closed.
+//!
+//! Version 4 adds streamed results: a callee started by `call_stream` sends
+//! any number of `Yield` frames on one connection, then ends the stream with
+//! a `Return` on the same connection or by closing it.
+//!
+//! Version 5 adds streamed arguments: a callee reads a `Value::Stream` it was
+//! passed by connecting to the socket and sending a `StreamRequest`, which
+//! the caller answers with a `Yield` frame per value and a final `Return`.
+//!
+//! Payloads are JSON unless the caller asked for another codec in
+//! `CAAT_CODEC`, see the `codec` module. The content type byte says which
+//! codec a payload is in.
+
+use std::io::{self, prelude::*};
+use crate::error::CaatError;
+
+pub const MAGIC: [u8; 4] = *b"CAAT";
+/// The newest protocol version this library speaks.
+pub const PROTOCOL_VERSION: u8 = 5;
+/// The oldest protocol version this library still speaks.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+/// The oldest protocol version with callback frames.
+pub const CALLBACK_VERSION: u8 = 2;
+/// The oldest protocol version with worker frames.
+pub const WORKER_VERSION: u8 = 3;
+/// The oldest protocol version with streamed results.
+pub const STREAM_VERSION: u8 = 4;
+/// The oldest protocol version with streamed arguments.
+pub const STREAM_ARGUMENT_VERSION: u8 = 5;
+pub(crate) const PROTOCOL_VAR: &str = "CAAT_PROTOCOL";
+
+const HEADER_LEN: usize = 12;
+
+/// How the payload of a frame is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentType {
+    /// The tagged JSON form produced by `Value::to_json`.
+    Json,
+    /// MessagePack, see `codec::Codec::MessagePack`.
+    MessagePack,
+    /// CBOR, see `codec::Codec::Cbor`.
+    Cbor,
+}
+
+impl ContentType {
+    fn to_byte(self) -> u8 {
+        match self {
+            ContentType::Json => 1,
+            ContentType::MessagePack => 2,
+            ContentType::Cbor => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<ContentType> {
+        match byte {
+            1 => Some(ContentType::Json),
+            2 => Some(ContentType::MessagePack),
+            3 => Some(ContentType::Cbor),
+            _ => None,
+        }
+    }
+}
+
+/// What a frame means.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    /// The return value of the call.
+    Return,
+    /// A callee asking the caller to run a callback. The payload is an object
+    /// with the callback id, the arguments and the callee's nesting depth.
+    CallbackRequest,
+    /// The result of a callback.
+    CallbackResponse,
+    /// A worker announcing it is ready for calls. The payload is empty.
+    Ready,
+    /// A call sent to a worker. The payload is the list of arguments.
+    Call,
+    /// Asks a worker to exit. The payload is empty.
+    Shutdown,
+    /// One value of a streamed result or argument.
+    Yield,
+    /// A callee asking for the values of a streamed argument. The payload is
+    /// an object with the stream id.
+    StreamRequest,
+}
+
+impl FrameKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            FrameKind::Return => 1,
+            FrameKind::CallbackRequest => 2,
+            FrameKind::CallbackResponse => 3,
+            FrameKind::Ready => 4,
+            FrameKind::Call => 5,
+            FrameKind::Shutdown => 6,
+            FrameKind::Yield => 7,
+            FrameKind::StreamRequest => 8,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<FrameKind> {
+        match byte {
+            1 => Some(FrameKind::Return),
+            2 => Some(FrameKind::CallbackRequest),
+            3 => Some(FrameKind::CallbackResponse),
+            4 => Some(FrameKind::Ready),
+            5 => Some(FrameKind::Call),
+            6 => Some(FrameKind::Shutdown),
+            7 => Some(FrameKind::Yield),
+            8 => Some(FrameKind::StreamRequest),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Frame {
+    pub(crate) version: u8,
+    pub(crate) content_type: ContentType,
+    pub(crate) kind: FrameKind,
+    pub(crate) payload: Vec<u8>,
+}
+
+/// The first thing read from a connection.
+#[derive(Debug)]
+pub(crate) enum Message {
+    /// The connection was closed without anything being written.
+    Empty,
+    /// An unframed JSON value, from a callee that does not speak the framed
+    /// protocol.
+    Legacy(Vec<u8>),
+    Frame(Frame),
+}
+
+/// Picks the protocol version to answer a caller with, given the value of
+/// `CAAT_PROTOCOL`. `None` means the caller only understands bare JSON.
+pub(crate) fn negotiate(advertised: Option<&str>) -> Option<u8> {
+    let theirs = advertised?.trim().parse::<u8>().ok()?;
+    if theirs < MIN_PROTOCOL_VERSION {
+        return None;
+    }
+    Some(theirs.min(PROTOCOL_VERSION))
+}
+
+/// Returns the protocol version to use when answering the caller of this
+/// process.
+pub(crate) fn negotiated_version() -> Option<u8> {
+    negotiate(std::env::var(PROTOCOL_VAR).ok().as_deref())
+}
+
+pub(crate) fn write_frame<W: Write>(writer: &mut W, version: u8, content_type: ContentType, kind: FrameKind, payload: &[u8]) -> io::Result<()> {
+    let length = u32::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame payload is larger than 4 GiB"))?;
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4] = version;
+    header[5] = content_type.to_byte();
+    header[6] = kind.to_byte();
+    header[8..].copy_from_slice(&length.to_be_bytes());
+    writer.write_all(&header)?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+/// Reads the first message of a connection, telling framed and legacy
+/// callees apart by the magic bytes.
+pub(crate) fn read_message<R: Read>(reader: &mut R) -> Result<Message, CaatError> {
+    let mut prefix = Vec::with_capacity(MAGIC.len());
+    reader.by_ref().take(MAGIC.len() as u64).read_to_end(&mut prefix).map_err(read_error)?;
+    if prefix.is_empty() {
+        return Ok(Message::Empty);
+    }
+    if prefix != MAGIC {
+        reader.read_to_end(&mut prefix).map_err(read_error)?;
+        return Ok(Message::Legacy(prefix));
+    }
+    read_frame_body(reader).map(Message::Frame)
+}
+
+fn read_frame_body<R: Read>(reader: &mut R) -> Result<Frame, CaatError> {
+    let mut header = [0; HEADER_LEN - 4];
+    reader.read_exact(&mut header).map_err(read_error)?;
+    let version = header[0];
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+        return Err(CaatError::IncompatibleVersion { ours: PROTOCOL_VERSION, theirs: version });
+    }
+    let content_type = ContentType::from_byte(header[1])
+        .ok_or_else(|| CaatError::ProtocolError(format!("unknown content type {}", header[1])))?;
+    let kind = FrameKind::from_byte(header[2])
+        .ok_or_else(|| CaatError::ProtocolError(format!("unknown frame kind {}", header[2])))?;
+    let length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let mut payload = Vec::new();
+    reader.by_ref().take(length as u64).read_to_end(&mut payload).map_err(read_error)?;
+    if payload.len() != length {
+        return Err(CaatError::ProtocolError(format!(
+            "connection closed after {} of {} payload bytes", payload.len(), length
+        )));
+    }
+    Ok(Frame { version, content_type, kind, payload })
+}
+
+fn read_error(e: io::Error) -> CaatError {
+    CaatError::ProtocolError(format!("failed to read from socket: {}", e))
+}
@@ -1,13 +1,72 @@
-use std::process::Command;
-use std::sync::Arc;
-use interprocess::local_socket;
-use std::io::prelude::*;
+pub mod arrow;
+#[cfg(all(feature = "tokio", unix))]
+pub mod async_caat;
+pub mod callback;
+pub mod callee;
+pub mod cancel;
+pub mod codec;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod error;
+pub mod format;
+pub mod frame;
+pub mod native;
+pub mod options;
+pub mod pool;
+mod process;
+pub mod query;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod signature;
+pub mod socket;
+pub mod stream;
+pub mod timestamp;
+pub mod typed;
+pub mod worker;
+
+use std::process::{Command, Stdio};
+use std::sync::{Arc, OnceLock};
+use interprocess::local_socket::LocalSocketListener;
 use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use json::JsonValue;
+pub use num_bigint::BigInt;
+pub use rust_decimal::Decimal;
 use std::fmt::{self};
-use std::process::Child;
-#[cfg(windows)]
-use dirs_next::home_dir;
+use std::process::{Child, ExitStatus};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+pub use arrow::CaatExt;
+#[cfg(all(feature = "tokio", unix))]
+pub use async_caat::AsyncCaat;
+#[cfg(feature = "macros")]
+pub use caat_macros::main;
+pub use cancel::CancellationToken;
+pub use codec::Codec;
+#[cfg(feature = "serde")]
+pub use de::from_value;
+pub use error::CaatError;
+#[cfg(feature = "serde")]
+pub use error::SerdeError;
+pub use native::NativeFunction;
+pub use options::CallOptions;
+pub use pool::{PoolOptions, PoolStats, WorkerPool};
+#[cfg(feature = "serde")]
+pub use ser::to_value;
+pub use typed::{IntoArgs, TypedForeignFunction};
+pub use worker::Worker;
+pub use callee::{serve_loop, yield_value};
+pub use stream::ValueStream;
+pub use timestamp::Timestamp;
+use callback::{Callback, Callbacks, Nesting};
+use frame::{FrameKind, Message};
+use process::{ChildWatch, Event, StderrCapture};
+use signature::Signature;
+use socket::SocketFile;
+use stream::Stream;
 
 const SOCKET_VAR: &str = "CAAT_SOCKET";
 const ARGS_VAR: &str = "CAAT_ARGS";
@@ -16,209 +75,281 @@ const ARGS_VAR: &str = "CAAT_ARGS";
 #[derive(Clone)]
 pub enum Value {
     Integer(i64),
+    /// An integer of any size, written as a string of digits on the wire.
+    /// Conversions only produce one for integers outside of `i64`.
+    BigInt(BigInt),
+    /// An exact decimal number, written as a string on the wire.
+    Decimal(Decimal),
     String(String),
+    /// Raw bytes, written as base64 in JSON.
+    Bytes(Vec<u8>),
     Float(f64),
+    /// An instant in UTC, written as RFC 3339 on the wire.
+    Timestamp(Timestamp),
+    /// A span of time, written as an ISO 8601 duration on the wire.
+    Duration(Duration),
     Map(HashMap<String, Value>, Option<String>),
     List(Box<[Value]>),
     Boolean(bool),
     Null,
     CAATFunction(Arc<dyn Caat + Send + Sync>),
     Failure(String),
+    /// Values produced as they are read, see `stream::Stream`.
+    Stream(Stream),
 }
 
 impl Value {
+    /// Returns the format tag of a map, or `None` for untagged maps and other
+    /// values.
+    pub fn map_format(&self) -> Option<&str> {
+        match self {
+            Value::Map(_, format) => format.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Sets the format tag of a map. Other values are returned unchanged.
+    pub fn with_format<S: Into<String>>(self, format: S) -> Value {
+        match self {
+            Value::Map(d, _) => Value::Map(d, Some(format.into())),
+            value => value,
+        }
+    }
+
+    /// A `Value::List` of `items`.
+    ///
+    /// Unlike `Value::from`, this names the element type, so an empty
+    /// `Value::list(vec![])` needs no annotation.
+    pub fn list(items: Vec<Value>) -> Value {
+        Value::List(items.into_boxed_slice())
+    }
+
+    /// A `Value::BigInt`, or a `Value::Integer` if `i` fits in an `i64`.
+    /// Decoders use this so a number decodes to the same variant however the
+    /// sender tagged it.
+    pub(crate) fn big_int(i: BigInt) -> Value {
+        match i64::try_from(&i) {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::BigInt(i),
+        }
+    }
+
+    /// Checks a tagged map against its registered format.
+    ///
+    /// Untagged maps and non-map values are always valid.
+    pub fn validate_format(&self) -> Result<(), format::FormatError> {
+        match self {
+            Value::Map(d, Some(f)) => format::validate(f, d),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the tagged JSON form of this value, see `to_json_value`.
+    ///
+    /// **This consumes any `Value::Stream` in the value.** A stream is read to
+    /// the end and written as a list, and since clones of a stream share its
+    /// values they are used up for every clone too. Never call this on a
+    /// stream that does not end.
     pub fn to_json(&self) -> String {
+        self.to_json_value().dump()
+    }
+
+    /// Builds the tagged JSON form of this value.
+    ///
+    /// Map keys are written in sorted order so the same value always encodes to
+    /// the same string. Floats that JSON cannot represent are written as the
+    /// strings `"NaN"`, `"Infinity"` and `"-Infinity"`.
+    ///
+    /// **This consumes any `Value::Stream` in the value**, as `to_json` does.
+    pub fn to_json_value(&self) -> JsonValue {
+        self.encode(None)
+    }
+
+    /// Builds the tagged JSON form, registering functions that are not
+    /// commands in `callbacks` so the callee can call back into them, and
+    /// streams so it can read them. Without `callbacks` streams are read to
+    /// the end and written as lists.
+    pub(crate) fn encode(&self, callbacks: Option<&Callbacks>) -> JsonValue {
         match self {
-            Value::Integer(i) => format!("{{\"type\": \"Integer\", \"value\": {}}}", i),
-            Value::String(s) => format!("{{\"type\": \"String\", \"value\": \"{}\"}}", s),
-            Value::Float(f) => format!("{{\"type\": \"Float\", \"value\": {}}}", f),
-            Value::Map(d, Some(format)) => {
-                let mut result = String::from("{");
-                for (key, value) in d {
-                    result.push_str(&format!("\"{}\": {}, ", key, value.to_json()));
-                }
-                result.pop();
-                result.pop();
-                result.push_str("}");
-                format!("{{\"type\": \"Map\", \"value\": {}, \"format\": {}}}", result, format)
+            Value::Integer(i) => Value::tagged("Integer", (*i).into()),
+            Value::BigInt(i) => Value::tagged("BigInt", i.to_string().into()),
+            Value::Decimal(d) => Value::tagged("Decimal", d.to_string().into()),
+            Value::Timestamp(t) => Value::tagged("Timestamp", t.to_string().into()),
+            Value::Duration(d) => Value::tagged("Duration", Value::Duration(*d).to_string().into()),
+            Value::String(s) => Value::tagged("String", s.as_str().into()),
+            Value::Bytes(b) => Value::tagged("Bytes", BASE64.encode(b).into()),
+            Value::Float(f) => {
+                let value = if f.is_nan() {
+                    "NaN".into()
+                } else if f.is_infinite() && f.is_sign_positive() {
+                    "Infinity".into()
+                } else if f.is_infinite() {
+                    "-Infinity".into()
+                } else {
+                    (*f).into()
+                };
+                Value::tagged("Float", value)
             }
-            Value::Map(d, None) => {
-                let mut result = String::from("{");
-                for (key, value) in d {
-                    result.push_str(&format!("\"{}\": {}, ", key, value.to_json()));
+            Value::Map(d, format) => {
+                let mut keys = d.keys().collect::<Vec<&String>>();
+                keys.sort();
+                let mut object = JsonValue::new_object();
+                for key in keys {
+                    object[key.as_str()] = d[key].encode(callbacks);
+                }
+                let mut result = Value::tagged("Map", object);
+                if let Some(format) = format {
+                    result["format"] = format.as_str().into();
                 }
-                result.pop();
-                result.pop();
-                result.push_str("}");
-                format!("{{\"type\": \"Map\", \"value\": {}}}", result)
+                result
             }
             Value::List(l) => {
-                let mut result = String::from("[");
-                for value in l.into_iter() {
-                    result.push_str(&format!("{}, ", value.to_json()));
+                let list = l.iter().map(|value| value.encode(callbacks)).collect::<Vec<JsonValue>>();
+                Value::tagged("List", JsonValue::Array(list))
+            }
+            Value::Boolean(b) => Value::tagged("Boolean", (*b).into()),
+            Value::Null => Value::tagged("Null", JsonValue::Null),
+            Value::CAATFunction(f) => {
+                let mut result = Value::tagged("CAAT", f.to_string().into());
+                match (f.as_foreign(), callbacks, f.callback_id()) {
+                    (None, Some(callbacks), _) => result["callback"] = callbacks.register(f.clone()).into(),
+                    // Without a registry the value goes back to the caller of
+                    // this process, which knows its callbacks by id.
+                    (None, None, Some(id)) => result["callback"] = id.into(),
+                    _ => (),
                 }
-                result.pop();
-                result.pop();
-                result.push_str("]");
-                format!("{{\"type\": \"List\", \"value\": {}}}", result)
+                result
             }
-            Value::Boolean(b) => format!("{{\"type\": \"Boolean\", \"value\": {}}}", b),
-            Value::Null => format!("{{\"type\": \"Null\", \"value\": null}}"),
-            Value::CAATFunction(s) => format!("{{\"type\": \"CAAT\", \"value\": \"{}\"}}", s),
-            Value::Failure(msg) => format!("{{\"type\": \"Failure\", \"value\": \"{}\"}}", msg),
+            Value::Failure(msg) => Value::tagged("Failure", msg.as_str().into()),
+            Value::Stream(stream) => match callbacks {
+                Some(callbacks) => Value::tagged("Stream", callbacks.register_stream(stream.clone()).into()),
+                None => Value::tagged("List", JsonValue::Array(stream.clone().map(|value| value.encode(None)).collect())),
+            },
         }
     }
 
+    #[inline]
+    fn tagged(the_type: &str, value: JsonValue) -> JsonValue {
+        let mut object = JsonValue::new_object();
+        object["type"] = the_type.into();
+        object["value"] = value;
+        object
+    }
+
     pub fn as_json(value: &[Value]) -> String {
-        let mut result = String::from("[");
-        for v in value {
-            result.push_str(&v.to_json());
-            result.push_str(", ");
-        }
+        let list = value.iter().map(Value::to_json_value).collect::<Vec<JsonValue>>();
+        JsonValue::Array(list).dump()
+    }
 
-        result.pop();
-        result.pop();
-        
-        result.push_str("]");
-        return result;
+    /// Parses a value from its tagged JSON string form.
+    pub fn from_json(string: &str) -> Option<Value> {
+        match json::parse(string) {
+            Ok(json) => Value::from_json_value(&json),
+            Err(_) => None,
+        }
     }
-    
+
     pub fn from_json_value(value: &JsonValue) -> Option<Value> {
-        match json::parse(&value.to_string()).unwrap() {
-            JsonValue::Object(o) => {
-                if let Some(value) = o.get("type") {
-                    if let Some(the_type) = value.as_str() {
-                        match the_type {
-                            "Integer" => {
-                                if let Some(value) = o.get("value") {
-                                    if let Some(i) = value.as_i64() {
-                                        return Some(Value::Integer(i));
-                                    } else {
-                                        return Some(Value::Integer(0));
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            "Float" => {
-                                if let Some(value) = o.get("value") {
-                                    if let Some(f) = value.as_f64() {
-                                        return Some(Value::Float(f));
-                                    } else {
-                                        return Some(Value::Float(0.0));
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            "String" => {
-                                if let Some(value) = o.get("value") {
-                                    if let Some(s) = value.as_str() {
-                                        return Some(Value::String(s.to_string()));
-                                    } else {
-                                        return Some(Value::String(String::new()));
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            "Map" => {
-                                if let Some(value) = o.get("value") {
-                                    match value {
-                                        JsonValue::Object(o) => {
-                                            let mut map = HashMap::new();
-                                            for (key, value) in o.iter() {
-                                                map.insert(key.to_string(), Value::from_json_value(value)?);
-                                            }
-                                            if let Some(format) = o.get("format") {
-                                                if let Some(s) = format.as_str() {
-                                                    return Some(Value::Map(map, Some(s.to_string())));
-                                                } else {
-                                                    return Some(Value::Map(map, None));
-                                                }
-                                            } else {
-                                                return Some(Value::Map(map, None));
-                                            }
-                                        }
-                                        _ => None
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            "List" => {
-                                if let Some(value) = o.get("value") {
-                                    match value {
-                                        JsonValue::Array(a) => {
-                                            let mut list = Vec::new();
-                                            for value in a.iter() {
-                                                list.push(Value::from_json_value(value)?);
-                                            }
-                                            Some(Value::List(list.into_boxed_slice()))
-                                        }
-                                        _ => None
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            "CAAT" => {
-                                if let Some(value) = o.get("value") {
-                                    if let Some(command) = value.as_str() {
-                                        return Some(Value::CAATFunction(Arc::new(ForeignFunction::new(command))));
-                                    } else {
-                                        return None;
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            "Boolean" => {
-                                if let Some(value) = o.get("value") {
-                                    if let Some(b) = value.as_bool() {
-                                        return Some(Value::Boolean(b));
-                                    } else {
-                                        return Some(Value::Boolean(false));
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            "Null" => {
-                                if let Some(value) = o.get("value") {
-                                    if value.is_null() {
-                                        return Some(Value::Null);
-                                    } else {
-                                        return None;
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            "Failure" => {
-                                if let Some(value) = o.get("value") {
-                                    if let Some(s) = value.as_str() {
-                                        return Some(Value::Failure(s.to_string()));
-                                    } else {
-                                        return Some(Value::Failure(String::new()));
-                                    }
-                                } else {
-                                    return None;
-                                }
-                            },
-                            _ => None
+        Value::decode(value, None)
+    }
 
+    /// Parses the tagged JSON form. A function carrying a callback id becomes
+    /// the function registered under that id in `callbacks` or, without
+    /// `callbacks`, a `Callback` into the caller of this process. Streams are
+    /// resolved the same way.
+    pub(crate) fn decode(value: &JsonValue, callbacks: Option<&Callbacks>) -> Option<Value> {
+        let o = match value {
+            JsonValue::Object(o) => o,
+            _ => return None,
+        };
+        let the_type = o.get("type")?.as_str()?;
+        let value = o.get("value")?;
+        match the_type {
+            "Integer" => value.as_i64().map(Value::Integer),
+            "Float" => {
+                let f = match value {
+                    JsonValue::Number(n) => n.to_string().parse::<f64>().ok()?,
+                    _ => match value.as_str() {
+                        Some("NaN") => f64::NAN,
+                        Some("Infinity") => f64::INFINITY,
+                        Some("-Infinity") => f64::NEG_INFINITY,
+                        _ => return None,
+                    },
+                };
+                Some(Value::Float(f))
+            }
+            "BigInt" => value.as_str()?.parse().ok().map(Value::big_int),
+            "Decimal" => value.as_str()?.parse().ok().map(Value::Decimal),
+            "Timestamp" => value.as_str()?.parse().ok().map(Value::Timestamp),
+            "Duration" => timestamp::parse_duration(value.as_str()?).map(Value::Duration),
+            "String" => value.as_str().map(|s| Value::String(s.to_string())),
+            "Bytes" => BASE64.decode(value.as_str()?).ok().map(Value::Bytes),
+            "Map" => {
+                match value {
+                    JsonValue::Object(inner) => {
+                        let mut map = HashMap::new();
+                        let mut format = o.get("format").and_then(|f| f.as_str()).map(|f| f.to_string());
+                        for (key, value) in inner.iter() {
+                            // The old decoder looked for the format tag among the
+                            // entries rather than beside them, so peers written
+                            // against it may put it there. It is a bare string
+                            // there, which can never be a tagged value.
+                            if key == "format" && value.is_string() {
+                                format = format.or_else(|| value.as_str().map(|f| f.to_string()));
+                                continue;
+                            }
+                            map.insert(key.to_string(), Value::decode(value, callbacks)?);
+                        }
+                        Some(Value::Map(map, format))
+                    }
+                    _ => None
+                }
+            }
+            "List" => {
+                match value {
+                    JsonValue::Array(a) => {
+                        let mut list = Vec::new();
+                        for value in a.iter() {
+                            list.push(Value::decode(value, callbacks)?);
                         }
-                    } else {
-                        None
+                        Some(Value::List(list.into_boxed_slice()))
                     }
+                    _ => None
+                }
+            }
+            "CAAT" => Some(Value::decode_function(value.as_str()?, o.get("callback").and_then(JsonValue::as_usize), callbacks)),
+            "Boolean" => value.as_bool().map(Value::Boolean),
+            "Null" => {
+                if value.is_null() {
+                    Some(Value::Null)
                 } else {
                     None
                 }
             }
+            "Failure" => value.as_str().map(|msg| Value::Failure(msg.to_string())),
+            "Stream" => Value::decode_stream(value.as_usize()?, callbacks),
             _ => None
         }
-            
+    }
+
+    /// Resolves a decoded function, which is a callback if it carries an id.
+    pub(crate) fn decode_function(command: &str, callback: Option<usize>, callbacks: Option<&Callbacks>) -> Value {
+        match (callback, callbacks) {
+            (Some(id), Some(callbacks)) => match callbacks.get(id) {
+                Some(function) => Value::CAATFunction(function),
+                None => Value::CAATFunction(Arc::new(ForeignFunction::new(command))),
+            },
+            (Some(id), None) => Callback::decode(id, command),
+            (None, _) => Value::CAATFunction(Arc::new(ForeignFunction::new(command))),
+        }
+    }
+
+    /// Resolves a decoded stream from its id.
+    pub(crate) fn decode_stream(id: usize, callbacks: Option<&Callbacks>) -> Option<Value> {
+        match callbacks {
+            Some(callbacks) => callbacks.stream(id).map(Value::Stream),
+            None => Some(Stream::decode(id)),
+        }
     }
 }
 
@@ -226,14 +357,19 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Value::Integer(i) => write!(f, "{}", i),
+            Value::BigInt(i) => write!(f, "{}", i),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Timestamp(t) => write!(f, "{}", t),
+            Value::Duration(d) => timestamp::format_duration(f, d),
             Value::String(s) => write!(f, "{}", s),
+            Value::Bytes(b) => write!(f, "{}", b.escape_ascii()),
             Value::Float(fl) => write!(f, "{}", fl),
             Value::Map(d, Some(format)) => {
                 write!(f, "{{")?;
                 for (key, value) in d {
                     write!(f, "\"{}\": {}, ", key, value)?;
                 }
-                write!(f, "\"format\": {}", format);
+                write!(f, "\"format\": {}", format)?;
                 write!(f, "}}")
             }
             Value::Map(d, None) => {
@@ -254,6 +390,7 @@ impl fmt::Display for Value {
             Value::Null => write!(f, "null"),
             Value::CAATFunction(s) => write!(f, "{}", s),
             Value::Failure(msg) => write!(f, "Falure: {}", msg),
+            Value::Stream(_) => write!(f, "<stream>"),
         }
     }
 }
@@ -262,14 +399,19 @@ impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Value::Integer(i) => write!(f, "Integer({})", i),
+            Value::BigInt(i) => write!(f, "BigInt({})", i),
+            Value::Decimal(d) => write!(f, "Decimal({})", d),
+            Value::Timestamp(t) => write!(f, "Timestamp({})", t),
+            Value::Duration(d) => write!(f, "Duration({:?})", d),
             Value::String(s) => write!(f, "String({})", s),
+            Value::Bytes(b) => write!(f, "Bytes({})", b.escape_ascii()),
             Value::Float(fl) => write!(f, "Float({})", fl),
             Value::Map(d, Some(format)) => {
                 write!(f, "Map(")?;
                 for (key, value) in d {
                     write!(f, "\"{}\": {}, ", key, value)?;
                 }
-                write!(f, ", {}", format);
+                write!(f, ", {}", format)?;
                 write!(f, ")")
             }
             Value::Map(d, None) => {
@@ -290,6 +432,7 @@ impl fmt::Debug for Value {
             Value::Null => write!(f, "Null"),
             Value::CAATFunction(s) => write!(f, "Function({})", s),
             Value::Failure(msg) => write!(f, "Failure({})", msg),
+            Value::Stream(_) => write!(f, "Stream"),
         }
     }
 }
@@ -298,12 +441,20 @@ impl PartialEq for Value {
     fn eq(&self, other: &Value) -> bool {
         match (self, other) {
             (Value::Integer(i), Value::Integer(j)) => i == j,
+            (Value::BigInt(i), Value::BigInt(j)) => i == j,
+            (Value::Decimal(d), Value::Decimal(e)) => d == e,
+            (Value::Timestamp(t), Value::Timestamp(u)) => t == u,
+            (Value::Duration(d), Value::Duration(e)) => d == e,
             (Value::String(s), Value::String(t)) => s == t,
+            (Value::Bytes(b), Value::Bytes(c)) => b == c,
             (Value::Float(f), Value::Float(g)) => f == g,
-            (Value::Map(d, _), Value::Map(e, _)) => d == e,
+            (Value::Map(d, f), Value::Map(e, g)) => d == e && f == g,
             (Value::List(l), Value::List(m)) => l == m,
             (Value::Boolean(b), Value::Boolean(c)) => b == c,
             (Value::Null, Value::Null) => true,
+            (Value::CAATFunction(f), Value::CAATFunction(g)) => f.to_string() == g.to_string(),
+            (Value::Failure(m), Value::Failure(n)) => m == n,
+            (Value::Stream(s), Value::Stream(t)) => s.ptr_eq(t),
             _ => false,
         }
     }
@@ -327,9 +478,57 @@ impl From<u32> for Value {
     }
 }
 
+/// Integers that do not fit in an `i64` become `Value::BigInt`.
 impl From<u64> for Value {
     fn from(i: u64) -> Self {
-        Value::Integer(i as i64)
+        match i64::try_from(i) {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::BigInt(BigInt::from(i)),
+        }
+    }
+}
+
+impl From<u128> for Value {
+    fn from(i: u128) -> Self {
+        match i64::try_from(i) {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::BigInt(BigInt::from(i)),
+        }
+    }
+}
+
+impl From<i128> for Value {
+    fn from(i: i128) -> Self {
+        match i64::try_from(i) {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::BigInt(BigInt::from(i)),
+        }
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(i: BigInt) -> Self {
+        Value::BigInt(i)
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(d)
+    }
+}
+
+/// Times outside of the years 0 to 9999 cannot be values, see `Timestamp`.
+impl TryFrom<SystemTime> for Value {
+    type Error = &'static str;
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        Timestamp::try_from(t).map(Value::Timestamp)
+    }
+}
+
+impl From<Duration> for Value {
+    fn from(d: Duration) -> Self {
+        Value::Duration(d)
     }
 }
 
@@ -415,130 +614,134 @@ impl From<Box<[Value]>> for Value {
     }
 }
 
-impl From<Vec<Value>> for Value {
-    fn from(l: Vec<Value>) -> Self {
-        Value::List(l.into_boxed_slice())
+/// Vectors become lists, except for `Vec<u8>`, which becomes `Value::Bytes`.
+///
+/// There is one impl per element type, including vectors of those types, so
+/// the element type of an empty `vec![]` cannot be inferred. Use
+/// `Value::list(vec![])` for an empty list of values.
+macro_rules! list_from {
+    ($($ty:ty),+) => {
+        $(
+            impl From<Vec<$ty>> for Value {
+                fn from(l: Vec<$ty>) -> Self {
+                    Value::List(l.into_iter().map(Value::from).collect())
+                }
+            }
+        )+
+    };
+}
+
+list_from!(Value, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64, String, &str, bool, ());
+list_from!(BigInt, Decimal, Timestamp, Duration);
+list_from!(HashMap<String, Value>, Box<[Value]>, Vec<Value>, Vec<u8>, OsString, PathBuf, Stream);
+list_from!(Vec<u16>, Vec<u32>, Vec<u64>, Vec<u128>, Vec<i8>, Vec<i16>, Vec<i32>, Vec<i64>, Vec<i128>);
+list_from!(Vec<f32>, Vec<f64>, Vec<String>, Vec<&str>, Vec<bool>, Vec<BigInt>, Vec<Decimal>);
+list_from!(Vec<Timestamp>, Vec<Duration>, Vec<HashMap<String, Value>>, Vec<OsString>, Vec<PathBuf>);
+
+impl From<Vec<u8>> for Value {
+    fn from(b: Vec<u8>) -> Self {
+        Value::Bytes(b)
     }
 }
 
-impl TryFrom<Value> for u8 {
-    type Error = &'static str;
-    fn try_from(value: Value) -> Result<Self, Self::Error> {
-        match value {
-            Value::Integer(i) => {
-                if i < 0 || i > u8::MAX as i64 {
-                    Err("Value is out of range")
-                } else {
-                    Ok(i as u8)
-                }
-            }
-            _ => Err("Value is not an integer"),
-        }
+impl From<&[u8]> for Value {
+    fn from(b: &[u8]) -> Self {
+        Value::Bytes(b.to_vec())
     }
 }
 
-impl TryFrom<Value> for u16 {
-    type Error = &'static str;
-    fn try_from(value: Value) -> Result<Self, Self::Error> {
-        match value {
-            Value::Integer(i) => {
-                if i < 0 || i > u16::MAX as i64 {
-                    Err("Value is out of range")
-                } else {
-                    Ok(i as u16)
-                }
-            }
-            _ => Err("Value is not an integer"),
+/// Valid UTF-8 becomes a `Value::String`. Anything else becomes
+/// `Value::Bytes` on Unix, so it converts back unchanged, and is converted
+/// lossily elsewhere.
+impl From<OsString> for Value {
+    fn from(s: OsString) -> Self {
+        match s.into_string() {
+            Ok(s) => Value::String(s),
+            #[cfg(unix)]
+            Err(s) => Value::Bytes(std::os::unix::ffi::OsStringExt::into_vec(s)),
+            #[cfg(not(unix))]
+            Err(s) => Value::String(s.to_string_lossy().into_owned()),
         }
     }
 }
 
-impl TryFrom<Value> for u32 {
-    type Error = &'static str;
-    fn try_from(value: Value) -> Result<Self, Self::Error> {
-        match value {
-            Value::Integer(i) => {
-                if i < 0 || i > u32::MAX as i64 {
-                    Err("Value is out of range")
-                } else {
-                    Ok(i as u32)
-                }
-            }
-            _ => Err("Value is not an integer"),
-        }
+impl From<&OsStr> for Value {
+    fn from(s: &OsStr) -> Self {
+        Value::from(s.to_os_string())
     }
 }
 
-impl TryFrom<Value> for u64 {
-    type Error = &'static str;
-    fn try_from(value: Value) -> Result<Self, Self::Error> {
-        match value {
-            Value::Integer(i) => {
-                if i < 0 {
-                    Err("Value is out of range")
-                } else {
-                    Ok(i as u64)
+impl From<PathBuf> for Value {
+    fn from(p: PathBuf) -> Self {
+        Value::from(p.into_os_string())
+    }
+}
+
+impl From<&Path> for Value {
+    fn from(p: &Path) -> Self {
+        Value::from(p.as_os_str())
+    }
+}
+
+/// Integers convert from `Value::Integer` and `Value::BigInt`, failing
+/// rather than wrapping when the value does not fit.
+macro_rules! integer_try_from {
+    ($($ty:ty),+) => {
+        $(
+            impl TryFrom<Value> for $ty {
+                type Error = &'static str;
+                fn try_from(value: Value) -> Result<Self, Self::Error> {
+                    match value {
+                        Value::Integer(i) => <$ty>::try_from(i).map_err(|_| "Value is out of range"),
+                        Value::BigInt(i) => <$ty>::try_from(&i).map_err(|_| "Value is out of range"),
+                        _ => Err("Value is not an integer"),
+                    }
                 }
             }
-            _ => Err("Value is not an integer"),
-        }
-    }
+        )+
+    };
 }
 
-impl TryFrom<Value> for i8 {
+integer_try_from!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl TryFrom<Value> for BigInt {
     type Error = &'static str;
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Integer(i) => {
-                if i < i8::MIN as i64 || i > i8::MAX as i64 {
-                    Err("Value is out of range")
-                } else {
-                    Ok(i as i8)
-                }
-            }
+            Value::Integer(i) => Ok(BigInt::from(i)),
+            Value::BigInt(i) => Ok(i),
             _ => Err("Value is not an integer"),
         }
     }
 }
 
-impl TryFrom<Value> for i16 {
+impl TryFrom<Value> for Decimal {
     type Error = &'static str;
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Integer(i) => {
-                if i < i16::MIN as i64 || i > i16::MAX as i64 {
-                    Err("Value is out of range")
-                } else {
-                    Ok(i as i16)
-                }
-            }
-            _ => Err("Value is not an integer"),
+            Value::Decimal(d) => Ok(d),
+            Value::Integer(i) => Ok(Decimal::from(i)),
+            Value::BigInt(i) => i128::try_from(&i).ok()
+                .and_then(|i| Decimal::try_from_i128_with_scale(i, 0).ok())
+                .ok_or("Value is out of range"),
+            _ => Err("Value is not a decimal"),
         }
     }
 }
 
-impl TryFrom<Value> for i32 {
+impl TryFrom<Value> for SystemTime {
     type Error = &'static str;
     fn try_from(value: Value) -> Result<Self, Self::Error> {
-        match value {
-            Value::Integer(i) => {
-                if i < i32::MIN as i64 || i > i32::MAX as i64 {
-                    Err("Value is out of range")
-                } else {
-                    Ok(i as i32)
-                }
-            }
-            _ => Err("Value is not an integer"),
-        }
+        Timestamp::try_from(value)?.try_into().map_err(|_| "Value is out of range")
     }
 }
 
-impl TryFrom<Value> for i64 {
+impl TryFrom<Value> for Duration {
     type Error = &'static str;
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Integer(i) => Ok(i),
-            _ => Err("Value is not an integer"),
+            Value::Duration(d) => Ok(d),
+            _ => Err("Value is not a duration"),
         }
     }
 }
@@ -604,6 +807,7 @@ impl TryFrom<Value> for Box<[Value]> {
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
             Value::List(l) => Ok(l),
+            Value::Stream(s) => Ok(s.collect()),
             _ => Err("Value is not a list"),
         }
     }
@@ -614,11 +818,61 @@ impl TryFrom<Value> for Vec<Value> {
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
             Value::List(l) => Ok(l.into_vec()),
+            Value::Stream(s) => Ok(s.collect()),
             _ => Err("Value is not a list"),
         }
     }
 }
 
+impl From<Stream> for Value {
+    fn from(s: Stream) -> Self {
+        Value::Stream(s)
+    }
+}
+
+impl TryFrom<Value> for Stream {
+    type Error = &'static str;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Stream(s) => Ok(s),
+            Value::List(l) => Ok(Stream::new(l.into_vec())),
+            _ => Err("Value is not a stream or a list"),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<u8> {
+    type Error = &'static str;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bytes(b) => Ok(b),
+            Value::String(s) => Ok(s.into_bytes()),
+            _ => Err("Value is not bytes"),
+        }
+    }
+}
+
+impl TryFrom<Value> for OsString {
+    type Error = &'static str;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(OsString::from(s)),
+            #[cfg(unix)]
+            Value::Bytes(b) => Ok(std::os::unix::ffi::OsStringExt::from_vec(b)),
+            #[cfg(not(unix))]
+            Value::Bytes(b) => String::from_utf8(b).map(OsString::from).map_err(|_| "Value is not valid UTF-8"),
+            _ => Err("Value is not a string or bytes"),
+        }
+    }
+}
+
+impl TryFrom<Value> for PathBuf {
+    type Error = &'static str;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        OsString::try_from(value).map(PathBuf::from)
+    }
+}
+
 impl TryFrom<Value> for () {
     type Error = &'static str;
     fn try_from(value: Value) -> Result<Self, Self::Error> {
@@ -629,190 +883,359 @@ impl TryFrom<Value> for () {
     }
 }
 
+/// Lists and streams convert to vectors of any type that converts from each
+/// element, failing on the first element that does not. This mirrors
+/// `list_from!`, except that `Vec<u8>` is bytes and `Vec<Value>` takes any
+/// element as it is.
+macro_rules! list_try_from {
+    ($($ty:ty),+) => {
+        $(
+            impl TryFrom<Value> for Vec<$ty> {
+                type Error = &'static str;
+                fn try_from(value: Value) -> Result<Self, Self::Error> {
+                    Vec::<Value>::try_from(value)?.into_iter().map(<$ty>::try_from).collect()
+                }
+            }
+        )+
+    };
+}
+
+list_try_from!(u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64, String, bool, ());
+list_try_from!(BigInt, Decimal, Timestamp, Duration);
+list_try_from!(HashMap<String, Value>, Box<[Value]>, Vec<Value>, Vec<u8>, OsString, PathBuf, Stream);
+list_try_from!(Vec<u16>, Vec<u32>, Vec<u64>, Vec<u128>, Vec<i8>, Vec<i16>, Vec<i32>, Vec<i64>, Vec<i128>);
+list_try_from!(Vec<f32>, Vec<f64>, Vec<String>, Vec<bool>, Vec<BigInt>, Vec<Decimal>);
+list_try_from!(Vec<Timestamp>, Vec<Duration>, Vec<HashMap<String, Value>>, Vec<OsString>, Vec<PathBuf>);
+
 
 
 
 pub trait Caat: fmt::Display {
     fn call(&self, args: &[Value]) -> Value;
+
+    /// The command this function runs, if it is one. Functions that are not
+    /// commands are passed to callees as callbacks.
+    fn as_foreign(&self) -> Option<&ForeignFunction> {
+        None
+    }
+
+    /// The id of this function with the caller of this process, if it is a
+    /// callback into that caller. It is passed back to the caller by id.
+    #[doc(hidden)]
+    fn callback_id(&self) -> Option<usize> {
+        None
+    }
+
+    /// Calls the function, reporting failures as errors rather than as a
+    /// `Value::Failure`.
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        match self.call(args) {
+            Value::Failure(message) => Err(CaatError::RemoteFailure { message, data: None }),
+            value => Ok(value),
+        }
+    }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone)]
 pub struct ForeignFunction {
     pub name: String,
-    args: Vec<String>
+    args: Vec<String>,
+    signature: OnceLock<Signature>,
+    codec: Codec,
+}
+
+impl PartialEq for ForeignFunction {
+    fn eq(&self, other: &ForeignFunction) -> bool {
+        self.name == other.name && self.args == other.args
+    }
 }
 
 
 impl ForeignFunction {
-    pub fn new<S: ?Sized>(name: &S) -> ForeignFunction
-    where S: AsRef<str> {
+    pub fn new<S>(name: &S) -> ForeignFunction
+    where S: AsRef<str> + ?Sized {
     let split = name.as_ref().split_whitespace().collect::<Vec<&str>>();
     
     Self {
             name: split[0].to_string(),
-            args: split[1..].into_iter().map(|x| x.to_string()).collect(),
+            args: split[1..].iter().map(|x| x.to_string()).collect(),
+            signature: OnceLock::new(),
+            codec: Codec::Json,
+        }
+    }
+
+    /// Sets the signature arguments are checked against, instead of asking
+    /// the command for it.
+    pub fn with_signature(self, signature: Signature) -> ForeignFunction {
+        ForeignFunction { signature: OnceLock::from(signature), ..self }
+    }
+
+    /// Asks the callee to return values in `codec` rather than JSON, see the
+    /// `codec` module. Callees that do not support it still answer in JSON.
+    pub fn with_codec(self, codec: Codec) -> ForeignFunction {
+        ForeignFunction { codec, ..self }
+    }
+
+    /// The codec callees are asked to return values in.
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Asks the command for its signature, see the `signature` module.
+    ///
+    /// The command is run with `--caat-signature` as its last argument, so
+    /// only ask commands known to support it. The answer is kept, and from
+    /// then on the arguments of every call are checked against it before the
+    /// command is started.
+    pub fn signature(&self) -> Result<Signature, CaatError> {
+        if let Some(signature) = self.signature.get() {
+            return Ok(signature.clone());
+        }
+        let value = self.spawn_call(&[], &CallOptions::default(), true)?;
+        let signature = Signature::from_value(&value)
+            .ok_or_else(|| CaatError::ProtocolError(format!("`{}` did not answer with a signature", self)))?;
+        let _ = self.signature.set(signature.clone());
+        Ok(signature)
+    }
+
+    /// Checks `args` against the signature, if it is known. Calls do not ask
+    /// for a signature themselves, since that would run commands which do not
+    /// understand the question.
+    fn check_args(&self, args: &[Value]) -> Result<(), CaatError> {
+        match self.signature.get() {
+            Some(signature) => signature.check(args).map_err(CaatError::ArgumentMismatch),
+            None => Ok(()),
         }
     }
 }
 
 impl ForeignFunction {
 
-    #[inline]
-    fn open_socket(mut handle: Child, socket_path: &str) -> Value {
+    /// Calls the function with the given options.
+    ///
+    /// When the timeout expires or the cancellation token is triggered the
+    /// callee is terminated (unless `kill_on_timeout` is off for a timeout),
+    /// its socket is removed and `CaatError::Timeout` or `CaatError::Cancelled`
+    /// is returned.
+    ///
+    /// If the signature is known the arguments are checked against it first,
+    /// see `signature`.
+    pub fn call_with(&self, args: &[Value], options: &CallOptions) -> Result<Value, CaatError> {
+        self.check_args(args)?;
+        self.spawn_call(args, options, false)
+    }
 
-        let listener = match local_socket::LocalSocketListener::bind(socket_path) {
-            Ok(listener) => listener,
-            Err(e) => {
-                return ForeignFunction::cleanup(socket_path, &e.to_string());
-            }
-        };
-        match listener.set_nonblocking(true) {
-            Ok(_) => (),
-            Err(e) => {
-                return ForeignFunction::cleanup(socket_path, &e.to_string());
-            }
-        };
+    /// Runs the command once. With `introspect` set it is asked for its
+    /// signature rather than called.
+    fn spawn_call(&self, args: &[Value], options: &CallOptions, introspect: bool) -> Result<Value, CaatError> {
+        if let Some(error) = ForeignFunction::interrupted(options) {
+            return Err(error);
+        }
+        let nesting = Nesting::child(options.max_depth)?;
+        let socket_path = socket::socket_path().map_err(CaatError::SocketBind)?;
+        let (mut command, callbacks) = self.command(args, &socket_path, nesting);
+        if introspect {
+            // A command that does not understand the question should see an
+            // unknown option, not a call without arguments.
+            command.arg(signature::INTROSPECT_ARG);
+            command.env(signature::INTROSPECT_VAR, "1");
+            command.env_remove(ARGS_VAR);
+        }
 
-        let mut try_wait = 3;
-        let mut stream = loop {
-            match listener.accept() {
-                Ok(stream) => break stream,
-                Err(e) => {
-                    if e.kind() != std::io::ErrorKind::WouldBlock {
-                        return ForeignFunction::cleanup(socket_path, &e.to_string());
-                    }
+        let listener = LocalSocketListener::bind(socket_path.as_str()).map_err(CaatError::SocketBind)?;
+        let socket_file = SocketFile(socket_path);
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        let mut handle = command.spawn().map_err(CaatError::SpawnFailed)?;
+        let stderr = StderrCapture::start(&mut handle);
+
+        ForeignFunction::open_socket(handle, listener, &socket_file, stderr, Arc::new(callbacks), options, deadline)
+    }
+
+    /// Builds the command for a call whose return value is sent to
+    /// `socket_path`, along with the callbacks found in `args`.
+    fn command(&self, args: &[Value], socket_path: &str, nesting: Nesting) -> (Command, Callbacks) {
+        let mut command = Command::new(&self.name);
+        for arg in &self.args {
+            command.arg(arg);
+        }
+        for arg in args {
+            match arg {
+                Value::String(value) => command.arg(value),
+                // Writing a stream out would read it before the call starts.
+                Value::Stream(_) => command.arg(arg.to_string()),
+                // Raw bytes go on the command line as they are, unless they
+                // hold a NUL, which an argument cannot.
+                #[cfg(unix)]
+                Value::Bytes(bytes) if !bytes.contains(&0) => {
+                    command.arg(<OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(bytes))
                 }
-            }
-            if try_wait < 3 {
-                try_wait += 1;
-                std::thread::sleep(std::time::Duration::from_millis(100));
-                continue;
-            }
+                _ => command.arg(arg.to_json()),
+            };
+        }
+        let callbacks = Callbacks::default();
+        let json = String::from_utf8(self.encode_args(args, Codec::Json, &callbacks)).expect("JSON is UTF-8");
 
-            match handle.try_wait() {
-                Ok(Some(status)) => {
-                    if !status.success() {
-                        
-                        match listener.accept() {
-                            Ok(stream) => break stream,
-                            Err(e) => {
-                                if e.kind() != std::io::ErrorKind::WouldBlock {
-                                    return ForeignFunction::cleanup(socket_path, &e.to_string());
-                                }
-                            }
-                        }
-                        let _ = std::fs::remove_file(socket_path);
-                        return match status.code() {
-                            Some(code) => Value::Integer(code as i64),
-                            None => Value::Null,
-                        };
-                    } else {
-                        match listener.accept() {
-                            Ok(stream) => break stream,
-                            Err(e) => {
-                                if e.kind() != std::io::ErrorKind::WouldBlock {
-                                    return ForeignFunction::cleanup(socket_path, &e.to_string());
-                                }
-                            }
-                        }
-                        let _ = std::fs::remove_file(socket_path);
-                        return match status.code() {
-                            Some(code) => Value::Integer(code as i64),
-                            None => Value::Null,
-                        };
-                    }
-                },
-                Ok(None) => (),
-                Err(e) => panic!("Error waiting for process: {}", e),
-            }
-            try_wait = 0;
-        };
-        let _ = stream.set_nonblocking(true);
+        command.env(ARGS_VAR, &json);
+        command.env(SOCKET_VAR, socket_path);
+        command.env(frame::PROTOCOL_VAR, frame::PROTOCOL_VERSION.to_string());
+        command.env(callback::DEPTH_VAR, nesting.depth.to_string());
+        command.env(callback::MAX_DEPTH_VAR, nesting.max_depth.to_string());
+        command.env_remove(codec::CODEC_VAR);
+        if self.codec != Codec::Json {
+            command.env(codec::CODEC_VAR, self.codec.name());
+        }
+        command.env_remove(signature::INTROSPECT_VAR);
+        command.env_remove(worker::WORKER_VAR);
+        command.env_remove(stream::STREAM_VAR);
+        command.stderr(Stdio::piped());
+        (command, callbacks)
+    }
+
+    /// Encodes the arguments of a call, after the ones given with the name.
+    /// Functions among them are registered in `callbacks`.
+    fn encode_args(&self, args: &[Value], codec: Codec, callbacks: &Callbacks) -> Vec<u8> {
+        let fixed = self.args.iter().map(|arg| Value::String(arg.to_string()));
+        codec.encode_args(fixed.chain(args.iter().cloned()), callbacks)
+    }
+
+    /// Calls the function and reads the values it yields one at a time, see
+    /// the `stream` module.
+    pub fn call_stream(&self, args: &[Value]) -> ValueStream {
+        ValueStream::start(self, args)
+    }
 
-        let mut json_string = String::new();
-        let mut buffer = [0; 1024];
+    /// Starts the command as a persistent worker, see the `worker` module.
+    ///
+    /// Fails with `CaatError::Timeout` if the worker is not ready within
+    /// `worker::START_TIMEOUT`.
+    pub fn spawn_worker(&self) -> Result<Worker, CaatError> {
+        self.spawn_worker_with(&CallOptions::default())
+    }
+
+    /// Starts the command as a persistent worker, giving it `options.timeout`
+    /// to become ready instead of `worker::START_TIMEOUT`. `max_depth` applies
+    /// as for a call, the other options are not used.
+    pub fn spawn_worker_with(&self, options: &CallOptions) -> Result<Worker, CaatError> {
+        Worker::start(self.clone(), options)
+    }
+
+    /// Waits for the callee to return, exit, time out or be cancelled,
+    /// whichever comes first.
+    ///
+    /// The child and the socket are watched by background threads that report
+    /// to a channel, so nothing here polls: a fast callee is answered as soon as
+    /// it closes its connection.
+    #[inline]
+    fn open_socket(handle: Child, listener: LocalSocketListener, socket_file: &SocketFile, stderr: StderrCapture, callbacks: Arc<Callbacks>, options: &CallOptions, deadline: Option<Instant>) -> Result<Value, CaatError> {
+        let (sender, events) = mpsc::channel();
+        let child = ChildWatch::start(handle, sender.clone());
+        socket::serve(listener, sender.clone(), callbacks.clone());
+        let _registration = options.cancellation.as_ref().map(|token| {
+            let sender = sender.clone();
+            token.register(move || {
+                let _ = sender.send(Event::Cancelled);
+            })
+        });
+        drop(sender);
+
+        let mut status = None;
+        let mut response = None;
         loop {
-            match handle.try_wait() {
-                Ok(Some(status)) => {
-                    if !status.success() {
-                        let _ = std::fs::remove_file(socket_path);
-                        return match status.code() {
-                            Some(code) => Value::Integer(code as i64),
-                            None => Value::Null,
-                        };
-                    } 
-                },
-                Ok(None) => (),
-                Err(e) => return ForeignFunction::cleanup(socket_path, &e.to_string()),
-            }
-            let bytes = match stream.read(&mut buffer) {
-                Ok(bytes) => bytes,
-                Err(e) => {
-                    if e.kind() != std::io::ErrorKind::WouldBlock {
-                        let _ = std::fs::remove_file(socket_path);
-                        return Value::Failure(e.to_string());
+            let event = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match events.recv_timeout(remaining) {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => {
+                            let timeout = options.timeout.unwrap_or_default();
+                            return ForeignFunction::abort(&child, &events, options, CaatError::Timeout(timeout));
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
                     }
-                    continue;
                 }
+                None => match events.recv() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                },
             };
-            json_string.push_str(&String::from_utf8_lossy(&buffer[..bytes]));
-            if bytes < 1024 {
-                break;
+            match event {
+                Event::Exited(Ok(exit)) => {
+                    if response.is_none() {
+                        // The callee may have exited without ever connecting.
+                        socket_file.wake();
+                    }
+                    status = Some(exit);
+                }
+                Event::Exited(Err(e)) => {
+                    return Err(CaatError::ProtocolError(format!("failed to wait for command: {}", e)));
+                }
+                Event::Returned(Ok(message)) => response = Some(message),
+                Event::Returned(Err(error)) => {
+                    return ForeignFunction::abort(&child, &events, options, error);
+                }
+                Event::AcceptFailed(e) => {
+                    return ForeignFunction::abort(&child, &events, options, CaatError::Accept(e));
+                }
+                Event::Cancelled => {
+                    return ForeignFunction::abort(&child, &events, options, CaatError::Cancelled);
+                }
+                Event::Connected(..) => (),
+            }
+            if let (Some(status), Some(response)) = (status, &response) {
+                return ForeignFunction::finish(status, response, stderr, &callbacks);
             }
         }
-        let json = ForeignFunction::read_json(json_string);
-        let _ = handle.wait();
-
-        drop(stream);
-        let _ = std::fs::remove_file(socket_path);
+        Err(CaatError::ProtocolError("lost track of the command".to_string()))
+    }
 
-        match Value::from_json_value(&json) {
-            Some(value) => value,
-            None => Value::Failure("Failed to parse JSON".to_string()),
+    /// Works out the result of a call once the callee has both answered and
+    /// exited.
+    fn finish(status: ExitStatus, response: &Message, stderr: StderrCapture, callbacks: &Callbacks) -> Result<Value, CaatError> {
+        let decoded = match response {
+            Message::Empty => return process::exit_result(status, stderr).map(|_| Value::Null),
+            Message::Legacy(bytes) => Codec::Json.decode_response(bytes, Some(callbacks)),
+            Message::Frame(frame) if frame.kind == FrameKind::Return => codec::decode_frame(frame, Some(callbacks)),
+            Message::Frame(frame) => {
+                return Err(CaatError::ProtocolError(format!("expected a return value, got {:?}", frame.kind)));
+            }
+        };
+        match decoded {
+            Err(CaatError::DecodeError(_)) if !status.success() => {
+                process::exit_result(status, stderr).map(|_| Value::Null)
+            }
+            result => result,
         }
     }
-    
-    fn cleanup(socket_path: &str, reason: &str) -> Value {
-        let _ = std::fs::remove_file(socket_path);
-        return Value::Failure(reason.to_string());
+
+    /// Checks whether the call has been cancelled before it started.
+    fn interrupted(options: &CallOptions) -> Option<CaatError> {
+        match &options.cancellation {
+            Some(token) if token.is_cancelled() => Some(CaatError::Cancelled),
+            _ => None,
+        }
     }
 
-    #[inline]
-    fn read_json(string: String) -> JsonValue {
-        return string.into()
+    /// Ends an interrupted call, stopping the callee if the options say so.
+    fn abort(child: &ChildWatch, events: &Receiver<Event>, options: &CallOptions, error: CaatError) -> Result<Value, CaatError> {
+        match error {
+            CaatError::Timeout(_) if !options.kill_on_timeout => (),
+            _ => child.terminate(events, options.grace_period),
+        }
+        Err(error)
     }
 }
 
 impl Caat for ForeignFunction {
     fn call(&self, args: &[Value]) -> Value {
-        let mut command = Command::new(&self.name);
-        let mut new_args = Vec::new();
-        for arg in &self.args {
-            command.arg(arg);
-            new_args.push(Value::String(arg.to_string()));
-        }
-        for arg in args {
-            match arg {
-                Value::String(value) => command.arg(&value),
-                _ => command.arg(&arg.to_json()),
-            };
-        }
-        new_args.extend_from_slice(args);
-        let json = Value::as_json(&new_args);
+        self.try_call(args).unwrap_or_else(Value::from)
+    }
 
-        command.env(ARGS_VAR, &json);
-        let pid = std::process::id();
-        #[cfg(unix)]
-        let socket_path = format!("/tmp/caat_{}.sock", pid);
-        #[cfg(windows)]
-        let socket_path = format!("{}\\AppData\\Local\\Temp\\caat_{}.sock", home_dir(), pid);
-        command.env(SOCKET_VAR, &socket_path);
-        let handle = match command.spawn() {
-            Ok(handle) => handle,
-            Err(e) => return Value::Failure(e.to_string()),
-        };
+    fn as_foreign(&self) -> Option<&ForeignFunction> {
+        Some(self)
+    }
 
-        return ForeignFunction::open_socket(handle, &socket_path);
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        self.call_with(args, &CallOptions::default())
     }
 }
 
@@ -837,8 +1260,8 @@ impl Args {
 
     pub fn from_args() -> Args {
         let mut args = Vec::new();
-        for arg in std::env::args() {
-            args.push(Value::String(arg));
+        for arg in std::env::args_os() {
+            args.push(Value::from(arg));
         }
         Args { args }
     }
@@ -869,35 +1292,35 @@ impl DoubleEndedIterator for Args {
     }
 }
 
+/// Returns the arguments of this process.
+///
+/// They come from `CAAT_ARGS` when the program was started by a caller, and
+/// from the command line, program name first, otherwise. If `CAAT_ARGS` is set
+/// but is not a list of tagged values, the caller is answered with a
+/// `Value::Failure` saying so and the program exits.
 pub fn args() -> Args {
-    match std::env::var(ARGS_VAR) {
-        Ok(s) => {
-            let json = s.into();
-            Args::from_json(json)
-
-        },
-        Err(_) => Args::from_args(),
+    match callee::caller_args() {
+        Some(Ok(args)) => Args { args },
+        Some(Err(e)) => callee::answer(Value::Failure(e.to_string())),
+        None => Args::from_args(),
     }
-
 }
 
 
+/// Returns a value to the caller and exits.
+///
+/// The expression is converted with `Value::from`, so anything with a `From`
+/// impl for `Value` can be returned directly. If the value cannot be delivered
+/// the error is printed and the program exits with code 1.
 #[macro_export]
 macro_rules! return_caat {
     ($e:expr) => {
-        let json = $e.into();
-        let socket_path = match std::env::var(SOCKET_VAR) {
-            Ok(s) => s,
-            Err(_) => {
-                std::process::exit(0);
+        match $crate::callee::return_value($crate::Value::from($e)) {
+            Ok(never) => match never {},
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
             }
         }
-        let mut stream = std::os::unix::net::UnixStream::connect(&socket_path).unwrap();
-        stream.write_all(json.dump().as_bytes()).unwrap();
-        stream.shutdown(std::net::Shutdown::Both).unwrap();
-        std::process::exit(0);
     };
 }
-
-
-
@@ -0,0 +1,52 @@
+//! In-process functions behind the `Caat` trait.
+
+use std::fmt;
+use std::sync::Arc;
+use crate::{Caat, Value};
+
+type Function = dyn Fn(&[Value]) -> Value + Send + Sync;
+
+/// A Rust closure that can be used wherever a `Caat` is expected, so local
+/// logic and external commands can be mixed in pipelines or swapped for one
+/// another in tests.
+///
+/// The name is what the function displays as. Clones share the closure.
+#[derive(Clone)]
+pub struct NativeFunction {
+    name: String,
+    function: Arc<Function>,
+}
+
+impl NativeFunction {
+    pub fn new<S, F>(name: S, function: F) -> NativeFunction
+    where
+        S: Into<String>,
+        F: Fn(&[Value]) -> Value + Send + Sync + 'static,
+    {
+        NativeFunction { name: name.into(), function: Arc::new(function) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Caat for NativeFunction {
+    fn call(&self, args: &[Value]) -> Value {
+        (self.function)(args)
+    }
+}
+
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .finish()
+    }
+}
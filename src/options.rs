@@ -0,0 +1,71 @@
+//! Options controlling a single call.
+
+use std::time::Duration;
+use crate::cancel::CancellationToken;
+
+/// Options for `ForeignFunction::call_with`.
+///
+/// The default has no timeout and no cancellation token, which is how
+/// `Caat::call` behaves.
+#[derive(Clone, Debug)]
+pub struct CallOptions {
+    /// How long the callee may run before the call fails with
+    /// `CaatError::Timeout`. `None` waits forever.
+    pub timeout: Option<Duration>,
+    /// Whether the callee is terminated when the timeout expires. When this is
+    /// `false` the callee is left running in the background, where it is still
+    /// waited for so it does not linger as a zombie once it exits.
+    pub kill_on_timeout: bool,
+    /// How long a terminated callee gets between `SIGTERM` and `SIGKILL`.
+    pub grace_period: Duration,
+    /// A token that aborts the call when cancelled.
+    pub cancellation: Option<CancellationToken>,
+    /// How many levels deep calls may nest, counting this call and any calls
+    /// started from its callbacks. It can lower but not raise the limit
+    /// inherited from callers further up. `None` keeps the inherited limit,
+    /// which starts at `callback::DEFAULT_MAX_DEPTH`.
+    pub max_depth: Option<u32>,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        CallOptions {
+            timeout: None,
+            kill_on_timeout: true,
+            grace_period: Duration::from_secs(1),
+            cancellation: None,
+            max_depth: None,
+        }
+    }
+}
+
+impl CallOptions {
+    pub fn new() -> CallOptions {
+        CallOptions::default()
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> CallOptions {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn kill_on_timeout(mut self, kill: bool) -> CallOptions {
+        self.kill_on_timeout = kill;
+        self
+    }
+
+    pub fn grace_period(mut self, grace_period: Duration) -> CallOptions {
+        self.grace_period = grace_period;
+        self
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> CallOptions {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> CallOptions {
+        self.max_depth = Some(max_depth);
+        self
+    }
+}
@@ -0,0 +1,403 @@
+//! Pools of workers that answer calls in parallel.
+//!
+//! A `WorkerPool` keeps several workers of the same command, see the `worker`
+//! module, and hands each call to one that is free. Workers are started as
+//! calls need them, up to `PoolOptions::max_size`. Calls that find every
+//! worker busy wait in a queue for the next one to be freed.
+
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+use crate::{Caat, CaatError, ForeignFunction, Value, Worker};
+
+/// Options for `WorkerPool::new`.
+#[derive(Clone, Debug)]
+pub struct PoolOptions {
+    /// How many workers are kept running even when idle. They are started
+    /// along with the pool.
+    pub min_size: usize,
+    /// How many workers may run at once, busy or idle.
+    pub max_size: usize,
+    /// How long a worker may stay idle before it is shut down, unless the
+    /// pool would shrink below `min_size`. `None` keeps idle workers.
+    pub idle_timeout: Option<Duration>,
+    /// How many calls a worker answers before it is shut down and replaced
+    /// by a fresh one. `None` keeps workers for as long as they run.
+    pub max_calls: Option<usize>,
+    /// How many calls may wait for a free worker. Calls beyond that fail
+    /// right away with `CaatError::QueueFull`. `None` lets any number wait.
+    /// Calls made by `WorkerPool::map` always wait and are not counted.
+    pub queue_limit: Option<usize>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        PoolOptions {
+            min_size: 1,
+            max_size: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            idle_timeout: Some(Duration::from_secs(60)),
+            max_calls: None,
+            queue_limit: None,
+        }
+    }
+}
+
+impl PoolOptions {
+    pub fn new() -> PoolOptions {
+        PoolOptions::default()
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> PoolOptions {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> PoolOptions {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> PoolOptions {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    pub fn max_calls(mut self, max_calls: usize) -> PoolOptions {
+        self.max_calls = Some(max_calls);
+        self
+    }
+
+    pub fn queue_limit(mut self, queue_limit: usize) -> PoolOptions {
+        self.queue_limit = Some(queue_limit);
+        self
+    }
+}
+
+/// A snapshot of what a `WorkerPool` is doing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Workers running or being started.
+    pub workers: usize,
+    /// Workers waiting for a call.
+    pub idle: usize,
+    /// Workers answering a call or being started.
+    pub busy: usize,
+    /// Calls waiting for a free worker, including those of `map`.
+    pub queued: usize,
+    /// Calls answered so far.
+    pub calls: u64,
+    /// Calls that ended in an error, including remote failures.
+    pub failures: u64,
+    /// Workers started so far.
+    pub started: u64,
+    /// Workers shut down for being idle too long or answering `max_calls`.
+    pub retired: u64,
+}
+
+/// Several workers of one command, see the module documentation.
+///
+/// ```no_run
+/// use caat_rust::{Caat, ForeignFunction, PoolOptions, Value, WorkerPool};
+///
+/// let pool = WorkerPool::new(ForeignFunction::new("resize"), PoolOptions::new().max_size(8)).unwrap();
+/// let one = pool.try_call(&[Value::from("a.png")]);
+/// let all = pool.map(vec!["b.png", "c.png", "d.png"]);
+/// ```
+pub struct WorkerPool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    function: ForeignFunction,
+    options: PoolOptions,
+    state: Mutex<State>,
+    /// Signalled when a worker is freed or a slot for one opens up.
+    freed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Free workers, the longest idle first.
+    idle: Vec<Idle>,
+    workers: usize,
+    /// Calls waiting for a worker that count towards `queue_limit`.
+    queued: usize,
+    /// Calls from `map` waiting for a worker.
+    mapping: usize,
+    calls: u64,
+    failures: u64,
+    started: u64,
+    retired: u64,
+    /// Set by `WorkerPool::shutdown`, after which no workers are started.
+    closed: bool,
+}
+
+struct Idle {
+    worker: Worker,
+    calls: usize,
+    since: Instant,
+}
+
+impl WorkerPool {
+    /// Starts a pool of `function` workers, with the first `min_size` of them
+    /// started right away.
+    pub fn new(function: ForeignFunction, options: PoolOptions) -> Result<WorkerPool, CaatError> {
+        let max_size = options.max_size.max(1);
+        let options = PoolOptions { max_size, min_size: options.min_size.min(max_size), ..options };
+        let interval = options.idle_timeout
+            .map_or(Duration::from_secs(1), |timeout| (timeout / 2).min(Duration::from_secs(1)))
+            .max(Duration::from_millis(10));
+        let shared = Arc::new(Shared {
+            function,
+            options,
+            state: Mutex::new(State::default()),
+            freed: Condvar::new(),
+        });
+        shared.fill()?;
+        Shared::maintain_every(Arc::downgrade(&shared), interval);
+        Ok(WorkerPool { shared })
+    }
+
+    /// The command the workers run.
+    pub fn function(&self) -> &ForeignFunction {
+        &self.shared.function
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.shared.lock();
+        PoolStats {
+            workers: state.workers,
+            idle: state.idle.len(),
+            busy: state.workers - state.idle.len(),
+            queued: state.queued + state.mapping,
+            calls: state.calls,
+            failures: state.failures,
+            started: state.started,
+            retired: state.retired,
+        }
+    }
+
+    /// Calls the function once for every input, with the input as its only
+    /// argument, on as many workers at a time as the pool allows.
+    ///
+    /// Inputs are taken from the iterator only as workers become free, so a
+    /// long or endless iterator is not read ahead. The results are in the
+    /// order of the inputs. The calls wait for a free worker however long the
+    /// queue is, since `map` only ever has `max_size` of them going.
+    pub fn map<I>(&self, inputs: I) -> Vec<Result<Value, CaatError>>
+    where
+        I: IntoIterator,
+        I::Item: Into<Value>,
+        I::IntoIter: Send,
+    {
+        let inputs = Mutex::new(inputs.into_iter().enumerate());
+        let results = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for _ in 0..self.shared.options.max_size {
+                scope.spawn(|| loop {
+                    let next = inputs.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    let (index, input) = match next {
+                        Some(next) => next,
+                        None => return,
+                    };
+                    let result = self.call_on_worker(&[input.into()], false);
+                    results.lock().unwrap_or_else(|e| e.into_inner()).push((index, result));
+                });
+            }
+        });
+        let mut results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Shuts down every worker, waiting for each to exit.
+    ///
+    /// No calls can be running since this takes the pool, but the background
+    /// thread that keeps `min_size` workers may be starting one. That worker
+    /// is waited for and shut down too, and no more are started.
+    ///
+    /// Returns the first error met, after trying all of them.
+    pub fn shutdown(self) -> Result<(), CaatError> {
+        let mut stopping = Vec::new();
+        {
+            let mut state = self.shared.lock();
+            state.closed = true;
+            loop {
+                let idle = std::mem::take(&mut state.idle);
+                state.workers -= idle.len();
+                stopping.extend(idle);
+                if state.workers == 0 {
+                    break;
+                }
+                state = self.shared.freed.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+        }
+        let mut result = Ok(());
+        for idle in stopping {
+            let stopped = idle.worker.shutdown();
+            if result.is_ok() {
+                result = stopped;
+            }
+        }
+        result
+    }
+}
+
+impl WorkerPool {
+    /// Calls the function on a free worker. `limited` calls fail when the
+    /// queue is full rather than wait.
+    fn call_on_worker(&self, args: &[Value], limited: bool) -> Result<Value, CaatError> {
+        let (worker, calls) = self.shared.acquire(limited)?;
+        let result = worker.try_call(args);
+        self.shared.release(worker, calls + 1, result.is_err());
+        result
+    }
+}
+
+impl Caat for WorkerPool {
+    fn call(&self, args: &[Value]) -> Value {
+        self.try_call(args).unwrap_or_else(Value::from)
+    }
+
+    /// Calls the function on a free worker, waiting for one if they are all
+    /// busy.
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        self.call_on_worker(args, true)
+    }
+}
+
+impl fmt::Display for WorkerPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.shared.function)
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Takes a free worker, starting or waiting for one if there is none,
+    /// along with how many calls it has answered. Only `limited` calls are
+    /// held to `queue_limit`.
+    fn acquire(&self, limited: bool) -> Result<(Worker, usize), CaatError> {
+        let mut state = self.lock();
+        loop {
+            if let Some(idle) = state.idle.pop() {
+                return Ok((idle.worker, idle.calls));
+            }
+            if state.workers < self.options.max_size {
+                state.workers += 1;
+                drop(state);
+                return self.start().map(|worker| (worker, 0));
+            }
+            if !limited {
+                state.mapping += 1;
+                state = self.freed.wait(state).unwrap_or_else(|e| e.into_inner());
+                state.mapping -= 1;
+                continue;
+            }
+            if self.options.queue_limit.is_some_and(|limit| state.queued >= limit) {
+                return Err(CaatError::QueueFull(state.queued));
+            }
+            state.queued += 1;
+            state = self.freed.wait(state).unwrap_or_else(|e| e.into_inner());
+            state.queued -= 1;
+        }
+    }
+
+    /// Starts a worker in a slot already counted in `State::workers`, giving
+    /// the slot back if that fails.
+    fn start(&self) -> Result<Worker, CaatError> {
+        let started = self.function.spawn_worker();
+        let mut state = self.lock();
+        match started {
+            Ok(worker) => {
+                state.started += 1;
+                Ok(worker)
+            }
+            Err(e) => {
+                state.workers -= 1;
+                drop(state);
+                self.freed.notify_all();
+                Err(e)
+            }
+        }
+    }
+
+    /// Puts a worker back after a call, or retires it if it has answered
+    /// `max_calls`.
+    fn release(&self, worker: Worker, calls: usize, failed: bool) {
+        let mut state = self.lock();
+        state.calls += 1;
+        if failed {
+            state.failures += 1;
+        }
+        let retired = if self.options.max_calls.is_some_and(|max| calls >= max) {
+            state.workers -= 1;
+            state.retired += 1;
+            Some(worker)
+        } else {
+            state.idle.push(Idle { worker, calls, since: Instant::now() });
+            None
+        };
+        drop(state);
+        self.freed.notify_one();
+        // Shutting the worker down may take a while, so do it unlocked.
+        drop(retired);
+    }
+
+    /// Starts workers until there are `min_size` of them, unless the pool is
+    /// shut down.
+    fn fill(&self) -> Result<(), CaatError> {
+        loop {
+            {
+                let mut state = self.lock();
+                if state.closed || state.workers >= self.options.min_size {
+                    return Ok(());
+                }
+                state.workers += 1;
+            }
+            let worker = self.start()?;
+            self.lock().idle.insert(0, Idle { worker, calls: 0, since: Instant::now() });
+            self.freed.notify_all();
+        }
+    }
+
+    /// Shuts down the workers that have been idle for too long.
+    fn retire_idle(&self) {
+        let timeout = match self.options.idle_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let mut expired = Vec::new();
+        {
+            let mut state = self.lock();
+            while state.workers > self.options.min_size
+                && state.idle.first().is_some_and(|idle| idle.since.elapsed() >= timeout)
+            {
+                expired.push(state.idle.remove(0));
+                state.workers -= 1;
+                state.retired += 1;
+            }
+        }
+        drop(expired);
+    }
+
+    /// Retires idle workers and tops the pool up to `min_size` on a
+    /// background thread, until the pool is dropped.
+    fn maintain_every(shared: Weak<Shared>, interval: Duration) {
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let shared = match shared.upgrade() {
+                Some(shared) => shared,
+                None => return,
+            };
+            if shared.lock().closed {
+                return;
+            }
+            shared.retire_idle();
+            let _ = shared.fill();
+        });
+    }
+}
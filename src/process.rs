@@ -0,0 +1,188 @@
+//! Bookkeeping for the child process of a call.
+
+use std::io::prelude::*;
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use interprocess::local_socket::LocalSocketStream;
+use crate::error::CaatError;
+use crate::frame::{Frame, Message};
+
+/// At most this much of the callee's stderr is kept for error reports.
+const STDERR_LIMIT: usize = 64 * 1024;
+
+/// Copies the stderr of a child to our own stderr while keeping the tail of
+/// it for error reports.
+pub(crate) struct StderrCapture {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    done: Receiver<()>,
+}
+
+impl StderrCapture {
+    /// Starts draining the piped stderr of `child`.
+    pub(crate) fn start(child: &mut Child) -> StderrCapture {
+        StderrCapture::from_reader(child.stderr.take())
+    }
+
+    pub(crate) fn from_reader<R: Read + Send + 'static>(stderr: Option<R>) -> StderrCapture {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let (sender, done) = mpsc::channel();
+        if let Some(mut stderr) = stderr {
+            let buffer = buffer.clone();
+            std::thread::spawn(move || {
+                let mut chunk = [0; 4096];
+                loop {
+                    let bytes = match stderr.read(&mut chunk) {
+                        Ok(0) | Err(_) => break,
+                        Ok(bytes) => bytes,
+                    };
+                    let _ = std::io::stderr().write_all(&chunk[..bytes]);
+                    let mut buffer = buffer.lock().unwrap_or_else(|e| e.into_inner());
+                    buffer.extend_from_slice(&chunk[..bytes]);
+                    if buffer.len() > STDERR_LIMIT {
+                        let excess = buffer.len() - STDERR_LIMIT;
+                        buffer.drain(..excess);
+                    }
+                }
+                let _ = sender.send(());
+            });
+        }
+        StderrCapture { buffer, done }
+    }
+
+    /// Returns what was captured, waiting up to `wait` for the pipe to close.
+    ///
+    /// The wait is bounded because a grandchild may keep the pipe open long
+    /// after the callee itself exited.
+    pub(crate) fn finish(self, wait: Duration) -> String {
+        let _ = self.done.recv_timeout(wait);
+        let buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+}
+
+/// Turns the exit status of a callee that returned nothing into a result.
+pub(crate) fn exit_result(status: ExitStatus, stderr: StderrCapture) -> Result<(), CaatError> {
+    if status.success() {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+    #[cfg(not(unix))]
+    let signal = None;
+    Err(CaatError::NonZeroExit {
+        code: status.code(),
+        signal,
+        stderr: stderr.finish(Duration::from_millis(100)),
+    })
+}
+
+/// Something that happened to a running call.
+pub(crate) enum Event {
+    /// The callee exited.
+    Exited(io::Result<ExitStatus>),
+    /// The callee connected and sent its answer.
+    Returned(Result<Message, CaatError>),
+    /// Accepting the callee's connection failed.
+    AcceptFailed(io::Error),
+    /// The call's cancellation token was triggered.
+    Cancelled,
+    /// The callee opened a connection that stays open, such as a worker's
+    /// control connection, starting with the given frame.
+    Connected(LocalSocketStream, Frame),
+}
+
+/// A child process that is waited on by a background thread.
+///
+/// The thread sends `Event::Exited` once the child is gone, so the caller can
+/// wait for exit, a connection or a timeout all at once.
+pub(crate) struct ChildWatch {
+    #[cfg(unix)]
+    pid: u32,
+    #[cfg(unix)]
+    reaped: Arc<Mutex<bool>>,
+    #[cfg(not(unix))]
+    child: Arc<Mutex<Child>>,
+}
+
+impl ChildWatch {
+    #[cfg(unix)]
+    pub(crate) fn start(mut child: Child, events: Sender<Event>) -> ChildWatch {
+        let pid = child.id();
+        let reaped = Arc::new(Mutex::new(false));
+        let watch = ChildWatch { pid, reaped: reaped.clone() };
+        std::thread::spawn(move || {
+            // Wait without reaping first so the pid cannot be reused while
+            // `signal` may still send to it.
+            loop {
+                let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+                let result = unsafe {
+                    libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT)
+                };
+                if result == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    break;
+                }
+            }
+            let status = {
+                let mut reaped = reaped.lock().unwrap_or_else(|e| e.into_inner());
+                *reaped = true;
+                child.wait()
+            };
+            let _ = events.send(Event::Exited(status));
+        });
+        watch
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn start(child: Child, events: Sender<Event>) -> ChildWatch {
+        let child = Arc::new(Mutex::new(child));
+        let watch = ChildWatch { child: child.clone() };
+        std::thread::spawn(move || loop {
+            let status = child.lock().unwrap_or_else(|e| e.into_inner()).try_wait();
+            match status {
+                Ok(Some(status)) => break events.send(Event::Exited(Ok(status))),
+                Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+                Err(e) => break events.send(Event::Exited(Err(e))),
+            };
+        });
+        watch
+    }
+
+    #[cfg(unix)]
+    fn signal(&self, signal: libc::c_int) {
+        let reaped = self.reaped.lock().unwrap_or_else(|e| e.into_inner());
+        if !*reaped {
+            unsafe {
+                libc::kill(self.pid as libc::pid_t, signal);
+            }
+        }
+    }
+
+    /// Stops the callee, giving it `grace_period` to exit after `SIGTERM`
+    /// before it is killed. Platforms without signals kill it right away.
+    ///
+    /// Other events arriving in the meantime are dropped.
+    pub(crate) fn terminate(&self, events: &Receiver<Event>, grace_period: Duration) {
+        #[cfg(unix)]
+        {
+            self.signal(libc::SIGTERM);
+            let deadline = std::time::Instant::now() + grace_period;
+            loop {
+                let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                match events.recv_timeout(remaining) {
+                    Ok(Event::Exited(_)) => return,
+                    Ok(_) => continue,
+                    Err(_) => break,
+                }
+            }
+            self.signal(libc::SIGKILL);
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (events, grace_period);
+            let _ = self.child.lock().unwrap_or_else(|e| e.into_inner()).kill();
+        }
+    }
+}
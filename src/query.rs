@@ -0,0 +1,441 @@
+//! Reaching into nested values.
+//!
+//! `Value` can be indexed like JSON: `value["users"][0]["name"]` gives the
+//! name of the first user, or `Value::Null` when any step is missing. `get`
+//! and `get_mut` return an `Option` instead, and the `as_*` accessors borrow
+//! the contents of a variant without matching on it.
+//!
+//! Paths name a value several steps down at once. A path is a chain of keys
+//! and indexes such as `users[0].name`. Keys that are not plain words are
+//! quoted, as in `headers["content-type"]`. A `*` key or `[*]` index is a
+//! wildcard matching every element of a list, or every value of a map in key
+//! order, so `items[*].id` names the id of each item. `query` finds a single
+//! value, `query_all` every value a wildcard path matches, and `query_as` and
+//! `query_all_as` convert what they find. Their errors name the exact path
+//! that failed, such as `items[3].id`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops;
+use std::time::Duration;
+use crate::signature::Signature;
+use crate::{Decimal, Timestamp, Value};
+
+static NULL: Value = Value::Null;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryError {
+    /// The path is not well formed. `position` is the byte offset of the
+    /// problem in `path`.
+    Syntax { path: String, position: usize, reason: &'static str },
+    /// A key is not in its map, or an index is past the end of its list.
+    Missing { path: String },
+    /// The value at `path` is `found`, which has no keys or indexes to follow.
+    NotIndexable { path: String, found: &'static str },
+    /// A wildcard path was given to `query`, which finds a single value.
+    Ambiguous { path: String },
+    /// The value at `path` could not be converted to the requested type.
+    Conversion { path: String, reason: &'static str },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::Syntax { path, position, reason } => {
+                write!(f, "invalid path \"{}\" at byte {}: {}", path, position, reason)
+            }
+            QueryError::Missing { path } => write!(f, "nothing at {}", path),
+            QueryError::NotIndexable { path, found } => {
+                write!(f, "{} is {}, not a map or list", Self::name(path), found)
+            }
+            QueryError::Ambiguous { path } => {
+                write!(f, "path \"{}\" has a wildcard, use query_all", path)
+            }
+            QueryError::Conversion { path, reason } => write!(f, "{}: {}", Self::name(path), reason),
+        }
+    }
+}
+
+impl QueryError {
+    /// The empty path is the value queried itself.
+    fn name(path: &str) -> &str {
+        if path.is_empty() { "the value" } else { path }
+    }
+}
+
+impl Error for QueryError {}
+
+/// A key or position that `Value::get` looks up: a `&str` or `String` in a
+/// map, or a `usize` in a list.
+pub trait ValueIndex {
+    fn index_into<'a>(&self, value: &'a Value) -> Option<&'a Value>;
+    fn index_into_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value>;
+}
+
+impl ValueIndex for usize {
+    fn index_into<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        value.as_list()?.get(*self)
+    }
+
+    fn index_into_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        value.as_list_mut()?.get_mut(*self)
+    }
+}
+
+impl ValueIndex for str {
+    fn index_into<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        value.as_map()?.get(self)
+    }
+
+    fn index_into_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        value.as_map_mut()?.get_mut(self)
+    }
+}
+
+impl ValueIndex for String {
+    fn index_into<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.as_str().index_into(value)
+    }
+
+    fn index_into_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        self.as_str().index_into_mut(value)
+    }
+}
+
+impl<T: ValueIndex + ?Sized> ValueIndex for &T {
+    fn index_into<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        (**self).index_into(value)
+    }
+
+    fn index_into_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        (**self).index_into_mut(value)
+    }
+}
+
+/// Looks up a key, giving `Value::Null` when the value is not a map or the
+/// key is missing.
+impl ops::Index<&str> for Value {
+    type Output = Value;
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// Looks up a position, giving `Value::Null` when the value is not a list or
+/// the position is past its end.
+impl ops::Index<usize> for Value {
+    type Output = Value;
+    fn index(&self, index: usize) -> &Value {
+        self.get(index).unwrap_or(&NULL)
+    }
+}
+
+impl Value {
+    /// The entry of a map under a key, or the element of a list at a position.
+    pub fn get<I: ValueIndex>(&self, index: I) -> Option<&Value> {
+        index.index_into(self)
+    }
+
+    pub fn get_mut<I: ValueIndex>(&mut self, index: I) -> Option<&mut Value> {
+        index.index_into_mut(self)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The integer, if it is one that fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            Value::BigInt(i) => i64::try_from(i).ok(),
+            _ => None,
+        }
+    }
+
+    /// The integer, if it is one that fits in a `u64`.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Integer(i) => u64::try_from(*i).ok(),
+            Value::BigInt(i) => u64::try_from(i).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Decimal(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<Timestamp> {
+        match self {
+            Value::Timestamp(t) => Some(*t),
+            _ => None,
+        }
+    }
+
+    pub fn as_duration(&self) -> Option<Duration> {
+        match self {
+            Value::Duration(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&HashMap<String, Value>> {
+        match self {
+            Value::Map(d, _) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_map_mut(&mut self) -> Option<&mut HashMap<String, Value>> {
+        match self {
+            Value::Map(d, _) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The elements of a list. A `Value::Stream` is not a list until it is
+    /// read, so it gives `None`.
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut [Value]> {
+        match self {
+            Value::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    /// The value at a path without wildcards, such as `users[0].name`.
+    pub fn query(&self, path: &str) -> Result<&Value, QueryError> {
+        let segments = parse(path)?;
+        if segments.contains(&Segment::Wildcard) {
+            return Err(QueryError::Ambiguous { path: path.to_string() });
+        }
+        let mut found = Vec::new();
+        walk(self, &segments, &mut String::new(), &mut found)?;
+        Ok(found.remove(0).1)
+    }
+
+    pub fn query_mut(&mut self, path: &str) -> Result<&mut Value, QueryError> {
+        let segments = parse(path)?;
+        if segments.contains(&Segment::Wildcard) {
+            return Err(QueryError::Ambiguous { path: path.to_string() });
+        }
+        let mut value = self;
+        let mut at = String::new();
+        for segment in &segments {
+            let found = Signature::type_of(value);
+            value = match (segment, value) {
+                (Segment::Key(key), Value::Map(d, _)) => {
+                    segment.write(&mut at);
+                    d.get_mut(key).ok_or_else(|| QueryError::Missing { path: at.clone() })?
+                }
+                (Segment::Index(i), Value::List(l)) => {
+                    segment.write(&mut at);
+                    l.get_mut(*i).ok_or_else(|| QueryError::Missing { path: at.clone() })?
+                }
+                _ => return Err(QueryError::NotIndexable { path: at, found }),
+            };
+        }
+        Ok(value)
+    }
+
+    /// Every value a path matches, in order. Wildcards over maps go through
+    /// the keys in sorted order. A path without wildcards matches one value.
+    pub fn query_all(&self, path: &str) -> Result<Vec<&Value>, QueryError> {
+        let mut found = Vec::new();
+        walk(self, &parse(path)?, &mut String::new(), &mut found)?;
+        Ok(found.into_iter().map(|(_, value)| value).collect())
+    }
+
+    /// Converts the value at a path, naming the path if it does not convert.
+    pub fn query_as<T>(&self, path: &str) -> Result<T, QueryError>
+    where T: TryFrom<Value, Error = &'static str> {
+        let value = self.query(path)?;
+        T::try_from(value.clone()).map_err(|reason| QueryError::Conversion { path: path.to_string(), reason })
+    }
+
+    /// Converts every value a path matches, naming the first one that does
+    /// not convert, such as `items[3].id`.
+    pub fn query_all_as<T>(&self, path: &str) -> Result<Vec<T>, QueryError>
+    where T: TryFrom<Value, Error = &'static str> {
+        let mut found = Vec::new();
+        walk(self, &parse(path)?, &mut String::new(), &mut found)?;
+        found.into_iter()
+            .map(|(path, value)| T::try_from(value.clone()).map_err(|reason| QueryError::Conversion { path, reason }))
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl Segment {
+    /// Appends this step to a concrete path, quoting keys that are not plain
+    /// words.
+    fn write(&self, path: &mut String) {
+        match self {
+            Segment::Key(key) if !key.is_empty() && key != "*" && key.chars().all(is_word) => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            }
+            Segment::Key(key) => {
+                path.push('[');
+                path.push_str(&json::stringify(key.as_str()));
+                path.push(']');
+            }
+            Segment::Index(i) => path.push_str(&format!("[{}]", i)),
+            Segment::Wildcard => path.push_str("[*]"),
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    !matches!(c, '.' | '[' | ']' | '"' | '*') && !c.is_whitespace()
+}
+
+fn parse(path: &str) -> Result<Vec<Segment>, QueryError> {
+    let error = |position, reason| QueryError::Syntax { path: path.to_string(), position, reason };
+    let mut segments = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        let position = path.len() - rest.len();
+        if let Some(bracket) = rest.strip_prefix('[') {
+            if let Some(after) = bracket.strip_prefix("*]") {
+                segments.push(Segment::Wildcard);
+                rest = after;
+            } else if bracket.starts_with('"') {
+                let (key, after) = quoted(bracket).ok_or_else(|| error(position + 1, "unterminated quoted key"))?;
+                rest = after.strip_prefix(']').ok_or_else(|| error(path.len() - after.len(), "expected ']'"))?;
+                segments.push(Segment::Key(key));
+            } else {
+                let digits = bracket.find(|c: char| !c.is_ascii_digit()).unwrap_or(bracket.len());
+                let index = bracket[..digits].parse().map_err(|_| error(position + 1, "expected an index, '*' or a quoted key"))?;
+                rest = bracket[digits..].strip_prefix(']').ok_or_else(|| error(position + 1 + digits, "expected ']'"))?;
+                segments.push(Segment::Index(index));
+            }
+            continue;
+        }
+        let key = match rest.strip_prefix('.') {
+            Some(key) => key,
+            None if segments.is_empty() => rest,
+            None => return Err(error(position, "expected '.' or '['")),
+        };
+        let position = path.len() - key.len();
+        if let Some(after) = key.strip_prefix('*') {
+            segments.push(Segment::Wildcard);
+            rest = after;
+            continue;
+        }
+        let end = key.find(|c: char| !is_word(c)).unwrap_or(key.len());
+        if end == 0 {
+            return Err(error(position, "expected a key"));
+        }
+        segments.push(Segment::Key(key[..end].to_string()));
+        rest = &key[end..];
+    }
+    Ok(segments)
+}
+
+/// Reads a JSON string literal at the start of `s`, returning it and what
+/// follows.
+fn quoted(s: &str) -> Option<(String, &str)> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => {
+                let key = json::parse(&s[..=i]).ok()?;
+                return Some((key.as_str()?.to_string(), &s[i + 1..]));
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Follows `segments` from `value`, collecting what it reaches along with
+/// the concrete path to each.
+fn walk<'a>(value: &'a Value, segments: &[Segment], at: &mut String, found: &mut Vec<(String, &'a Value)>) -> Result<(), QueryError> {
+    let Some((segment, rest)) = segments.split_first() else {
+        found.push((at.clone(), value));
+        return Ok(());
+    };
+    let len = at.len();
+    match (segment, value) {
+        (Segment::Key(key), Value::Map(d, _)) => {
+            segment.write(at);
+            let next = d.get(key).ok_or_else(|| QueryError::Missing { path: at.clone() })?;
+            walk(next, rest, at, found)?;
+        }
+        (Segment::Index(i), Value::List(l)) => {
+            segment.write(at);
+            let next = l.get(*i).ok_or_else(|| QueryError::Missing { path: at.clone() })?;
+            walk(next, rest, at, found)?;
+        }
+        (Segment::Wildcard, Value::List(l)) => {
+            for (i, next) in l.iter().enumerate() {
+                Segment::Index(i).write(at);
+                walk(next, rest, at, found)?;
+                at.truncate(len);
+            }
+        }
+        (Segment::Wildcard, Value::Map(d, _)) => {
+            let mut keys = d.keys().collect::<Vec<_>>();
+            keys.sort();
+            for key in keys {
+                let segment = Segment::Key(key.clone());
+                segment.write(at);
+                walk(&d[key], rest, at, found)?;
+                at.truncate(len);
+            }
+        }
+        _ => return Err(QueryError::NotIndexable { path: at.clone(), found: Signature::type_of(value) }),
+    }
+    at.truncate(len);
+    Ok(())
+}
@@ -0,0 +1,354 @@
+//! Serializing Rust types into `Value`, enabled by the `serde` feature.
+//!
+//! Structs and maps become `Value::Map`, sequences and tuples become
+//! `Value::List`, `None` and `()` become `Value::Null`, and enums use the
+//! externally tagged layout: a unit variant is its name as a string, any other
+//! variant is a single entry map from its name to its content.
+//!
+//! `Value` itself serializes to the same shape. `Value::Failure`,
+//! `Value::CAATFunction`, `Value::BigInt`, `Value::Decimal`, `Value::Timestamp`
+//! and `Value::Duration` become newtype variants of the same names (`CAAT` for
+//! functions) holding their wire strings, which `to_value` turns back into the
+//! original variants. Integers outside of `i64` become `Value::BigInt`, and
+//! ones inside it `Value::Integer` even if they were a `Value::BigInt` before.
+//! The format tag of a map is not carried through serde, and a `Value::Stream`
+//! is read to the end and serialized as a sequence. Byte slices serialized
+//! with `serialize_bytes`, such as `serde_bytes` fields, become `Value::Bytes`
+//! and back.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::ser::{self, Serialize};
+use crate::error::SerdeError;
+use crate::{ForeignFunction, Value};
+
+/// The enum name `Value` uses for its `Failure` and `CAAT` variants, so that
+/// `Serializer` can tell them apart from user enums.
+pub(crate) const VALUE_ENUM: &str = "$caat::Value";
+
+/// Converts any serializable type into a `Value`.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, SerdeError> {
+    value.serialize(Serializer)
+}
+
+impl Serialize for Value {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::{SerializeMap, SerializeSeq};
+        match self {
+            Value::Integer(i) => serializer.serialize_i64(*i),
+            Value::BigInt(i) => serializer.serialize_newtype_variant(VALUE_ENUM, 2, "BigInt", &i.to_string()),
+            Value::Decimal(d) => serializer.serialize_newtype_variant(VALUE_ENUM, 3, "Decimal", &d.to_string()),
+            Value::Timestamp(_) => serializer.serialize_newtype_variant(VALUE_ENUM, 4, "Timestamp", &self.to_string()),
+            Value::Duration(_) => serializer.serialize_newtype_variant(VALUE_ENUM, 5, "Duration", &self.to_string()),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Bytes(b) => serializer.serialize_bytes(b),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::Map(d, _) => {
+                let mut map = serializer.serialize_map(Some(d.len()))?;
+                for (key, value) in d {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Value::List(l) => {
+                let mut seq = serializer.serialize_seq(Some(l.len()))?;
+                for value in l.iter() {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Null => serializer.serialize_unit(),
+            Value::CAATFunction(f) => serializer.serialize_newtype_variant(VALUE_ENUM, 0, "CAAT", &f.to_string()),
+            Value::Failure(msg) => serializer.serialize_newtype_variant(VALUE_ENUM, 1, "Failure", msg),
+            Value::Stream(stream) => {
+                // Reads the stream to the end, as encoding it as JSON does.
+                let mut seq = serializer.serialize_seq(None)?;
+                for value in stream.clone() {
+                    seq.serialize_element(&value)?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+/// A serializer whose output is a `Value`.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeVariant<SerializeList>;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeVariant<SerializeMap>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, SerdeError> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, SerdeError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, SerdeError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SerdeError> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value, SerdeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, SerdeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, SerdeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Value, SerdeError> {
+        Ok(Value::from(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Value, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<Value, SerdeError> {
+        let value = value.serialize(Serializer)?;
+        if name == VALUE_ENUM {
+            match (variant, value) {
+                ("Failure", Value::String(msg)) => return Ok(Value::Failure(msg)),
+                ("CAAT", Value::String(command)) => {
+                    return Ok(Value::CAATFunction(Arc::new(ForeignFunction::new(&command))));
+                }
+                ("BigInt", Value::String(i)) => return i.parse().map(Value::big_int).map_err(|e| SerdeError::new(format!("{}", e))),
+                ("Decimal", Value::String(d)) => return d.parse().map(Value::Decimal).map_err(|e| SerdeError::new(format!("{}", e))),
+                ("Timestamp", Value::String(t)) => return t.parse().map(Value::Timestamp).map_err(SerdeError::new),
+                ("Duration", Value::String(d)) => {
+                    return crate::timestamp::parse_duration(&d).map(Value::Duration)
+                        .ok_or_else(|| SerdeError::new("not an ISO 8601 duration"));
+                }
+                (_, value) => return Ok(tagged(variant, value)),
+            }
+        }
+        Ok(tagged(variant, value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, SerdeError> {
+        Ok(SerializeList(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeList, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<SerializeVariant<SerializeList>, SerdeError> {
+        Ok(SerializeVariant { variant, inner: SerializeList(Vec::with_capacity(len)) })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, SerdeError> {
+        Ok(SerializeMap { map: HashMap::new(), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<SerializeVariant<SerializeMap>, SerdeError> {
+        Ok(SerializeVariant { variant, inner: self.serialize_map(Some(len))? })
+    }
+}
+
+fn tagged(variant: &str, value: Value) -> Value {
+    let mut map = HashMap::new();
+    map.insert(variant.to_string(), value);
+    Value::Map(map, None)
+}
+
+pub struct SerializeList(Vec<Value>);
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.0.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(Value::from(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeMap {
+    map: HashMap<String, Value>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        let key = match key.serialize(Serializer)? {
+            Value::String(s) => s,
+            Value::Integer(i) => i.to_string(),
+            Value::BigInt(i) => i.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            other => return Err(SerdeError::new(format!("map key must be a string, got {:?}", other))),
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self.key.take()
+            .ok_or_else(|| SerdeError::new("map value serialized before its key"))?;
+        self.map.insert(key, value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(Value::Map(self.map, None))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.map.insert(key.to_string(), value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(Value::Map(self.map, None))
+    }
+}
+
+/// Collects the content of a tuple or struct variant and wraps it in a map
+/// keyed by the variant name.
+pub struct SerializeVariant<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeList> {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(tagged(self.variant, ser::SerializeSeq::end(self.inner)?))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeMap> {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(tagged(self.variant, ser::SerializeStruct::end(self.inner)?))
+    }
+}
@@ -0,0 +1,331 @@
+//! Asking a command what it takes and returns.
+//!
+//! A caller asks for a description instead of a call by setting
+//! `CAAT_INTROSPECT=1` and passing `--caat-signature` as the last argument,
+//! without any `CAAT_ARGS`. Callees written with `#[caat_rust::main]` answer
+//! it with their `Signature`, encoded as a map with the `caat-signature`
+//! format tag, and do not run. Other commands know nothing of the handshake
+//! and see an option they do not understand, which most refuse before doing
+//! any work. One that ignores unknown options runs anyway, so only ask
+//! commands known to support it.
+//!
+//! Arguments are only checked against a signature once it is known, either
+//! asked for with `ForeignFunction::signature` or given with
+//! `ForeignFunction::with_signature`. Calls never ask on their own.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use crate::callee::ArgumentError;
+use crate::stream::Stream;
+use std::time::{Duration, SystemTime};
+use crate::{BigInt, Decimal, Timestamp, Value};
+
+pub(crate) const INTROSPECT_VAR: &str = "CAAT_INTROSPECT";
+pub(crate) const INTROSPECT_ARG: &str = "--caat-signature";
+/// The format tag of an encoded `Signature`.
+pub const SIGNATURE_FORMAT: &str = "caat-signature";
+
+/// The kind of `Value` a parameter accepts or a command returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    /// Any value at all.
+    Any,
+    /// A `Value::Integer` or `Value::BigInt`.
+    Integer,
+    Decimal,
+    String,
+    Bytes,
+    Timestamp,
+    Duration,
+    Float,
+    Map,
+    List,
+    Boolean,
+    Null,
+    Function,
+    /// A `Value::Stream`, or a list read as one.
+    Stream,
+}
+
+impl ValueType {
+    /// Whether `value` is of this type.
+    pub fn matches(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (ValueType::Any, _)
+                | (ValueType::Integer, Value::Integer(_) | Value::BigInt(_))
+                | (ValueType::Decimal, Value::Decimal(_))
+                | (ValueType::String, Value::String(_))
+                | (ValueType::Bytes, Value::Bytes(_))
+                | (ValueType::Timestamp, Value::Timestamp(_))
+                | (ValueType::Duration, Value::Duration(_))
+                | (ValueType::Float, Value::Float(_))
+                | (ValueType::Map, Value::Map(..))
+                | (ValueType::List, Value::List(_) | Value::Stream(_))
+                | (ValueType::Boolean, Value::Boolean(_))
+                | (ValueType::Null, Value::Null)
+                | (ValueType::Function, Value::CAATFunction(_))
+                | (ValueType::Stream, Value::Stream(_) | Value::List(_))
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::Any => "any",
+            ValueType::Integer => "integer",
+            ValueType::Decimal => "decimal",
+            ValueType::String => "string",
+            ValueType::Bytes => "bytes",
+            ValueType::Timestamp => "timestamp",
+            ValueType::Duration => "duration",
+            ValueType::Float => "float",
+            ValueType::Map => "map",
+            ValueType::List => "list",
+            ValueType::Boolean => "boolean",
+            ValueType::Null => "null",
+            ValueType::Function => "function",
+            ValueType::Stream => "stream",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ValueType> {
+        match name {
+            "any" => Some(ValueType::Any),
+            "integer" => Some(ValueType::Integer),
+            "decimal" => Some(ValueType::Decimal),
+            "string" => Some(ValueType::String),
+            "bytes" => Some(ValueType::Bytes),
+            "timestamp" => Some(ValueType::Timestamp),
+            "duration" => Some(ValueType::Duration),
+            "float" => Some(ValueType::Float),
+            "map" => Some(ValueType::Map),
+            "list" => Some(ValueType::List),
+            "boolean" => Some(ValueType::Boolean),
+            "null" => Some(ValueType::Null),
+            "function" => Some(ValueType::Function),
+            "stream" => Some(ValueType::Stream),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub value_type: ValueType,
+}
+
+/// What a command takes and returns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Param>,
+    pub returns: ValueType,
+    pub description: Option<String>,
+    pub version: Option<String>,
+}
+
+impl Signature {
+    /// Checks that `args` fit the parameters.
+    pub fn check(&self, args: &[Value]) -> Result<(), ArgumentError> {
+        if args.len() != self.params.len() {
+            return Err(ArgumentError::Arity { expected: self.params.len(), found: args.len() });
+        }
+        for (position, (param, arg)) in self.params.iter().zip(args).enumerate() {
+            if !param.value_type.matches(arg) {
+                return Err(ArgumentError::Type {
+                    position: position + 1,
+                    name: param.name.clone(),
+                    expected: param.value_type.to_string(),
+                    reason: format!("got {}", Signature::type_of(arg)),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// How `value` is described in error messages, such as "a string".
+    pub(crate) fn type_of(value: &Value) -> &'static str {
+        match value {
+            Value::Integer(_) => "an integer",
+            Value::BigInt(_) => "a big integer",
+            Value::Decimal(_) => "a decimal",
+            Value::String(_) => "a string",
+            Value::Bytes(_) => "bytes",
+            Value::Timestamp(_) => "a timestamp",
+            Value::Duration(_) => "a duration",
+            Value::Float(_) => "a float",
+            Value::Map(..) => "a map",
+            Value::List(_) => "a list",
+            Value::Boolean(_) => "a boolean",
+            Value::Null => "null",
+            Value::CAATFunction(_) => "a function",
+            Value::Failure(_) => "a failure",
+            Value::Stream(_) => "a stream",
+        }
+    }
+
+    /// Encodes the signature as a map tagged with `SIGNATURE_FORMAT`.
+    pub fn to_value(&self) -> Value {
+        let params = self.params.iter()
+            .map(|param| Value::from(vec![
+                ("name".to_string(), Value::from(param.name.as_str())),
+                ("type".to_string(), Value::from(param.value_type.name())),
+            ]))
+            .collect::<Vec<Value>>();
+        let optional = |s: &Option<String>| s.as_deref().map_or(Value::Null, Value::from);
+        Value::from(vec![
+            ("params".to_string(), Value::from(params)),
+            ("returns".to_string(), Value::from(self.returns.name())),
+            ("description".to_string(), optional(&self.description)),
+            ("version".to_string(), optional(&self.version)),
+        ]).with_format(SIGNATURE_FORMAT)
+    }
+
+    /// Decodes a signature encoded by `to_value`.
+    pub fn from_value(value: &Value) -> Option<Signature> {
+        let map = match value {
+            Value::Map(map, Some(format)) if format == SIGNATURE_FORMAT => map,
+            _ => return None,
+        };
+        let value_type = |map: &HashMap<String, Value>, key: &str| match map.get(key) {
+            Some(Value::String(name)) => ValueType::from_name(name),
+            _ => None,
+        };
+        let optional = |key: &str| match map.get(key) {
+            Some(Value::String(s)) => Some(Some(s.clone())),
+            Some(Value::Null) | None => Some(None),
+            _ => None,
+        };
+        let params = match map.get("params") {
+            Some(Value::List(params)) => params.iter()
+                .map(|param| match param {
+                    Value::Map(param, _) => Some(Param {
+                        name: match param.get("name") {
+                            Some(Value::String(name)) => name.clone(),
+                            _ => return None,
+                        },
+                        value_type: value_type(param, "type")?,
+                    }),
+                    _ => None,
+                })
+                .collect::<Option<Vec<Param>>>()?,
+            _ => return None,
+        };
+        Some(Signature {
+            params,
+            returns: value_type(map, "returns")?,
+            description: optional("description")?,
+            version: optional("version")?,
+        })
+    }
+}
+
+/// Rust types that map to a single `ValueType`, used by `#[caat_rust::main]`
+/// to describe parameters and return types.
+pub trait Describe {
+    fn value_type() -> ValueType;
+
+    /// The type of a `Vec` of this type, which is a list except for bytes.
+    #[doc(hidden)]
+    fn list_type() -> ValueType {
+        ValueType::List
+    }
+}
+
+macro_rules! describe {
+    ($value_type:ident: $($ty:ty),+) => {
+        $(
+            impl Describe for $ty {
+                fn value_type() -> ValueType {
+                    ValueType::$value_type
+                }
+            }
+        )+
+    };
+}
+
+impl Describe for u8 {
+    fn value_type() -> ValueType {
+        ValueType::Integer
+    }
+
+    fn list_type() -> ValueType {
+        ValueType::Bytes
+    }
+}
+
+describe!(Integer: u16, u32, u64, u128, i8, i16, i32, i64, i128, BigInt);
+describe!(Decimal: Decimal);
+describe!(Timestamp: Timestamp, SystemTime);
+describe!(Duration: Duration);
+#[cfg(feature = "chrono")]
+describe!(Timestamp: chrono::DateTime<chrono::Utc>);
+#[cfg(feature = "chrono")]
+describe!(Duration: chrono::TimeDelta);
+#[cfg(feature = "time")]
+describe!(Timestamp: time::OffsetDateTime);
+#[cfg(feature = "time")]
+describe!(Duration: time::Duration);
+describe!(Float: f32, f64);
+describe!(String: String, &str);
+describe!(Boolean: bool);
+describe!(Null: ());
+describe!(Map: HashMap<String, Value>);
+describe!(List: Box<[Value]>);
+describe!(Any: Value);
+describe!(Stream: Stream);
+
+impl<T: Describe> Describe for Vec<T> {
+    fn value_type() -> ValueType {
+        T::list_type()
+    }
+}
+
+impl<T: Describe, E> Describe for Result<T, E> {
+    fn value_type() -> ValueType {
+        T::value_type()
+    }
+}
+
+/// Lets `#[caat_rust::main]` describe a type that may or may not implement
+/// `Describe`, falling back to `ValueType::Any`. Method resolution prefers
+/// `DescribeKnown` on `Probe<T>` when `T: Describe` and otherwise autorefs to
+/// `DescribeUnknown` on `&Probe<T>`.
+#[doc(hidden)]
+pub struct Probe<T: ?Sized>(PhantomData<T>);
+
+impl<T: ?Sized> Probe<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Probe<T> {
+        Probe(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait DescribeKnown {
+    fn value_type(&self) -> ValueType;
+}
+
+impl<T: Describe + ?Sized> DescribeKnown for Probe<T> {
+    fn value_type(&self) -> ValueType {
+        T::value_type()
+    }
+}
+
+#[doc(hidden)]
+pub trait DescribeUnknown {
+    fn value_type(&self) -> ValueType;
+}
+
+impl<T: ?Sized> DescribeUnknown for &Probe<T> {
+    fn value_type(&self) -> ValueType {
+        ValueType::Any
+    }
+}
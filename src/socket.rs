@@ -0,0 +1,194 @@
+//! Naming of the sockets callees return their values through.
+//!
+//! Every call gets its own socket so calls from several threads, or calls made
+//! from inside a callback, never fight over the same path. On Unix the sockets
+//! live in a private `caat-<uid>` directory inside the first of:
+//!
+//! 1. the directory given to [`set_socket_dir`],
+//! 2. `$CAAT_SOCKET_DIR`,
+//! 3. `$XDG_RUNTIME_DIR`,
+//! 4. the system temporary directory.
+//!
+//! The private directory is created with `0700` permissions if it does not
+//! exist. One that already exists must belong to the current user and be
+//! closed to everyone else, or calls fail with `CaatError::SocketBind`. The
+//! directory it is in is created if needed but its permissions are never
+//! changed.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, RwLock};
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use crate::callback::Callbacks;
+use crate::frame::{self, FrameKind, Message};
+use crate::process::Event;
+
+const SOCKET_DIR_VAR: &str = "CAAT_SOCKET_DIR";
+
+static SOCKET_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Overrides the directory sockets are created in for this process.
+pub fn set_socket_dir<P: Into<PathBuf>>(dir: P) {
+    let mut socket_dir = SOCKET_DIR.write().unwrap_or_else(|e| e.into_inner());
+    *socket_dir = Some(dir.into());
+}
+
+/// Returns the directory sockets are created in.
+pub fn socket_dir() -> PathBuf {
+    #[cfg(unix)]
+    let name = format!("caat-{}", unsafe { libc::getuid() });
+    #[cfg(not(unix))]
+    let name = "caat".to_string();
+    base_dir().join(name)
+}
+
+/// The directory the private socket directory is made in.
+fn base_dir() -> PathBuf {
+    if let Some(dir) = SOCKET_DIR.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return dir.clone();
+    }
+    if let Some(dir) = std::env::var_os(SOCKET_DIR_VAR) {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir);
+    }
+    std::env::temp_dir()
+}
+
+/// Picks a fresh socket path for one call.
+///
+/// The name combines the pid, a per-process counter and a random number, so
+/// it is unique within the process and unlikely to collide with a stale
+/// socket left behind by a process that had the same pid.
+pub(crate) fn socket_path() -> io::Result<String> {
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(count);
+    let name = format!("caat_{}_{}_{:016x}.sock", std::process::id(), count, hasher.finish());
+
+    #[cfg(unix)]
+    {
+        let dir = socket_dir();
+        create_private_dir(&dir)?;
+        Ok(dir.join(name).to_string_lossy().into_owned())
+    }
+    #[cfg(not(unix))]
+    return Ok(name);
+}
+
+/// Creates `dir` for the current user alone, or checks that it already is
+/// theirs alone. Permissions are never changed, since the directory may be
+/// shared.
+#[cfg(unix)]
+fn create_private_dir(dir: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    if let Some(parent) = dir.parent() {
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(parent)?;
+    }
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+        Err(e) => return Err(e),
+    }
+    // A symlink planted in a shared directory is not followed.
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is not a directory", dir.display())));
+    }
+    if metadata.uid() != unsafe { libc::getuid() } {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is owned by another user", dir.display())));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is open to other users", dir.display())));
+    }
+    Ok(())
+}
+
+/// Accepts the callee's connections on a background thread.
+///
+/// Connections that start with a callback or stream request are answered on
+/// threads of their own. The first one that does not carries the callee's answer: a
+/// single frame, or everything up to EOF from a legacy callee.
+pub(crate) fn serve(listener: LocalSocketListener, events: Sender<Event>, callbacks: Arc<Callbacks>) {
+    accept_loop(listener, events, move || callbacks.clone(), &[]);
+}
+
+/// Accepts the connections of a worker on a background thread.
+///
+/// The connection that starts with a `Ready` frame is handed over as the
+/// worker's control connection. Callback requests are answered with whatever
+/// callbacks the current call put in `callbacks`. Anything else means the
+/// callee is not a worker and ends the loop.
+pub(crate) fn serve_worker(listener: LocalSocketListener, events: Sender<Event>, callbacks: Arc<Mutex<Arc<Callbacks>>>) {
+    let callbacks = move || callbacks.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    accept_loop(listener, events, callbacks, &[FrameKind::Ready]);
+}
+
+/// Accepts the connections of a streaming call on a background thread.
+///
+/// The connection that starts with a `Yield` frame is handed over to read the
+/// rest of the stream from. A callee that returns without yielding anything
+/// answers as for `serve`.
+pub(crate) fn serve_stream(listener: LocalSocketListener, events: Sender<Event>, callbacks: Arc<Callbacks>) {
+    accept_loop(listener, events, move || callbacks.clone(), &[FrameKind::Yield]);
+}
+
+/// Accepts connections until one carries an answer. Connections starting
+/// with a frame of one of the `handed_over` kinds are sent on as
+/// `Event::Connected` without ending the loop.
+fn accept_loop<C>(listener: LocalSocketListener, events: Sender<Event>, callbacks: C, handed_over: &'static [FrameKind])
+where C: Fn() -> Arc<Callbacks> + Send + 'static {
+    std::thread::spawn(move || loop {
+        let mut stream = match listener.accept() {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = events.send(Event::AcceptFailed(e));
+                return;
+            }
+        };
+        match frame::read_message(&mut stream) {
+            Ok(Message::Frame(request)) if request.kind == FrameKind::CallbackRequest => {
+                let callbacks = callbacks();
+                std::thread::spawn(move || callbacks.serve(stream, request));
+            }
+            Ok(Message::Frame(request)) if request.kind == FrameKind::StreamRequest => {
+                let callbacks = callbacks();
+                std::thread::spawn(move || callbacks.send_stream(stream, request));
+            }
+            Ok(Message::Frame(first)) if handed_over.contains(&first.kind) => {
+                let _ = events.send(Event::Connected(stream, first));
+            }
+            message => {
+                let _ = events.send(Event::Returned(message));
+                return;
+            }
+        }
+    });
+}
+
+/// The socket of a call, removed when the call is over.
+pub(crate) struct SocketFile(pub(crate) String);
+
+impl SocketFile {
+    /// Unblocks a `serve` thread that is still waiting for a connection by
+    /// connecting to the socket ourselves. The thread then sees an empty
+    /// response.
+    pub(crate) fn wake(&self) {
+        let _ = LocalSocketStream::connect(self.0.as_str());
+    }
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        self.wake();
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
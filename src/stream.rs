@@ -0,0 +1,324 @@
+//! Streams of values going into and out of calls.
+//!
+//! `ForeignFunction::call_stream` starts the command with `CAAT_STREAM` set.
+//! The callee sends each value with `callee::yield_value` as soon as it has
+//! it, and the caller reads them one at a time as the stream is iterated, so
+//! neither side ever holds the whole result. A callee that gets ahead of its
+//! caller blocks until the caller catches up.
+//!
+//! The other way around, a `Stream` passed as an argument is not written into
+//! `CAAT_ARGS`. The callee gets a stream of its own that asks the caller for
+//! the values over the call's socket as it is iterated, so a filter program
+//! can work through an input of any size.
+
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use json::JsonValue;
+use crate::callback::{Callbacks, Nesting};
+use crate::codec::{self, Codec};
+use crate::frame::{self, ContentType, FrameKind, Message};
+use crate::process::{ChildWatch, Event, StderrCapture};
+use crate::socket::{self, SocketFile};
+use crate::{CaatError, ForeignFunction, Value, SOCKET_VAR};
+
+pub(crate) const STREAM_VAR: &str = "CAAT_STREAM";
+/// How long a callee that is stopped early gets to exit after `SIGTERM`.
+const STOP_GRACE: Duration = Duration::from_secs(1);
+
+/// The values a streaming call yields, see the module documentation.
+///
+/// Each item is a yielded value, or `CaatError::RemoteFailure` for a yielded
+/// `Value::Failure`. If the call ends in an error, such as the callee exiting
+/// unsuccessfully, that error is the last item. Dropping the stream before
+/// the end stops the callee.
+///
+/// ```no_run
+/// use caat_rust::{ForeignFunction, Value};
+///
+/// let mut rows = ForeignFunction::new("export-rows").call_stream(&[Value::from("users")]);
+/// for row in rows.by_ref().take(10) {
+///     println!("{}", row.unwrap());
+/// }
+/// ```
+pub struct ValueStream {
+    running: Option<Running>,
+    /// An error raised before the callee was started, returned as the only
+    /// item.
+    error: Option<CaatError>,
+    returned: Option<Value>,
+}
+
+impl ValueStream {
+    pub(crate) fn start(function: &ForeignFunction, args: &[Value]) -> ValueStream {
+        match Running::start(function, args) {
+            Ok(running) => ValueStream { running: Some(running), error: None, returned: None },
+            Err(error) => ValueStream { running: None, error: Some(error), returned: None },
+        }
+    }
+
+    /// The value the callee returned after its last yielded value, once the
+    /// stream has ended without an error. A callee that just exited returns
+    /// `Value::Null`.
+    pub fn returned(&self) -> Option<&Value> {
+        self.returned.as_ref()
+    }
+}
+
+impl Iterator for ValueStream {
+    type Item = Result<Value, CaatError>;
+
+    fn next(&mut self) -> Option<Result<Value, CaatError>> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        let running = self.running.as_mut()?;
+        let message = match running.read() {
+            Ok(Message::Frame(frame)) if frame.kind == FrameKind::Yield => {
+                return Some(codec::decode_frame(&frame, Some(&running.callbacks)));
+            }
+            Ok(message) => message,
+            Err(error) => {
+                self.running = None;
+                return Some(Err(error));
+            }
+        };
+        let running = self.running.take()?;
+        match running.finish(&message) {
+            Ok(value) => {
+                self.returned = Some(value);
+                None
+            }
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+/// The callee of a stream that has not ended yet.
+struct Running {
+    connection: Option<LocalSocketStream>,
+    callbacks: Arc<Callbacks>,
+    stderr: Option<StderrCapture>,
+    status: Option<ExitStatus>,
+    child: ChildWatch,
+    events: Receiver<Event>,
+    socket_file: SocketFile,
+}
+
+impl Running {
+    fn start(function: &ForeignFunction, args: &[Value]) -> Result<Running, CaatError> {
+        function.check_args(args)?;
+        let nesting = Nesting::child(None)?;
+        let socket_path = socket::socket_path().map_err(CaatError::SocketBind)?;
+        let (mut command, callbacks) = function.command(args, &socket_path, nesting);
+        command.env(STREAM_VAR, "1");
+
+        let listener = LocalSocketListener::bind(socket_path.as_str()).map_err(CaatError::SocketBind)?;
+        let socket_file = SocketFile(socket_path);
+        let mut handle = command.spawn().map_err(CaatError::SpawnFailed)?;
+        let stderr = StderrCapture::start(&mut handle);
+        let (sender, events) = mpsc::channel();
+        let child = ChildWatch::start(handle, sender.clone());
+        let callbacks = Arc::new(callbacks);
+        socket::serve_stream(listener, sender, callbacks.clone());
+        Ok(Running {
+            connection: None,
+            callbacks,
+            stderr: Some(stderr),
+            status: None,
+            child,
+            events,
+            socket_file,
+        })
+    }
+
+    /// Reads the next message of the stream, waiting for the callee to
+    /// connect first if it has not yet.
+    fn read(&mut self) -> Result<Message, CaatError> {
+        if let Some(connection) = self.connection.as_mut() {
+            return frame::read_message(connection);
+        }
+        loop {
+            match self.events.recv() {
+                Ok(Event::Connected(connection, first)) => {
+                    self.connection = Some(connection);
+                    return Ok(Message::Frame(first));
+                }
+                Ok(Event::Returned(message)) => return message,
+                Ok(Event::Exited(Ok(status))) => {
+                    // The callee may have exited without ever connecting.
+                    self.status = Some(status);
+                    self.socket_file.wake();
+                }
+                Ok(Event::Exited(Err(e))) => {
+                    return Err(CaatError::ProtocolError(format!("failed to wait for command: {}", e)));
+                }
+                Ok(Event::AcceptFailed(e)) => return Err(CaatError::Accept(e)),
+                Ok(Event::Cancelled) => (),
+                Err(_) => return Err(CaatError::ProtocolError("lost track of the command".to_string())),
+            }
+        }
+    }
+
+    /// Works out the result of the call from the message that ended the
+    /// stream, once the callee has exited.
+    fn finish(mut self, message: &Message) -> Result<Value, CaatError> {
+        let status = match self.status {
+            Some(status) => status,
+            None => self.wait()?,
+        };
+        self.status = Some(status);
+        let stderr = self.stderr.take().unwrap_or_else(|| StderrCapture::from_reader(None::<io::Empty>));
+        ForeignFunction::finish(status, message, stderr, &self.callbacks)
+    }
+
+    fn wait(&self) -> Result<ExitStatus, CaatError> {
+        loop {
+            match self.events.recv() {
+                Ok(Event::Exited(Ok(status))) => return Ok(status),
+                Ok(Event::Exited(Err(e))) => {
+                    return Err(CaatError::ProtocolError(format!("failed to wait for command: {}", e)));
+                }
+                Ok(_) => (),
+                Err(_) => return Err(CaatError::ProtocolError("lost track of the command".to_string())),
+            }
+        }
+    }
+}
+
+impl Drop for Running {
+    /// Stops a callee whose stream was not read to the end. Closing the
+    /// connection first makes a callee blocked on a yield fail right away.
+    fn drop(&mut self) {
+        if self.status.is_none() {
+            self.connection = None;
+            self.child.terminate(&self.events, STOP_GRACE);
+        }
+    }
+}
+
+/// A sequence of values produced as it is read, passed as `Value::Stream`.
+///
+/// Where a stream cannot be handed over lazily, such as in a return value or
+/// in `Value::to_json`, it is read to the end and sent as a list. Clones share
+/// the same values: a value taken through one clone is gone for all of them.
+///
+/// ```no_run
+/// use std::io::BufRead;
+/// use caat_rust::{Caat, ForeignFunction, Value};
+/// use caat_rust::stream::Stream;
+///
+/// let lines = std::io::BufReader::new(std::io::stdin()).lines().map(|line| Value::from(line.unwrap()));
+/// let matches = ForeignFunction::new("grep-values").call(&[Value::from("error"), Stream::new(lines).into()]);
+/// ```
+#[derive(Clone)]
+pub struct Stream(Arc<Mutex<Box<dyn Iterator<Item = Value> + Send>>>);
+
+impl Stream {
+    pub fn new<I>(values: I) -> Stream
+    where
+        I: IntoIterator,
+        I::Item: Into<Value> + 'static,
+        I::IntoIter: Send + 'static,
+    {
+        Stream(Arc::new(Mutex::new(Box::new(values.into_iter().map(Into::into)))))
+    }
+
+    /// Whether both streams are clones of the same one.
+    pub fn ptr_eq(&self, other: &Stream) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Builds the stream for an argument decoded in this process, read from
+    /// the caller of this process.
+    pub(crate) fn decode(id: usize) -> Value {
+        match std::env::var(SOCKET_VAR) {
+            Ok(socket) => Value::Stream(Stream::new(RemoteStream { id, socket, connection: None, done: false })),
+            Err(_) => Value::Stream(Stream::new([Value::Failure("there is no caller to read the stream from".to_string())])),
+        }
+    }
+}
+
+impl Iterator for Stream {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).next()
+    }
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stream")
+    }
+}
+
+/// The values of a stream the caller of this process passed in.
+///
+/// A stream that cannot be read, or is cut off, ends with a `Value::Failure`
+/// saying why.
+struct RemoteStream {
+    id: usize,
+    socket: String,
+    connection: Option<LocalSocketStream>,
+    done: bool,
+}
+
+impl RemoteStream {
+    fn connect(&self) -> Result<LocalSocketStream, CaatError> {
+        let version = frame::negotiated_version()
+            .filter(|version| *version >= frame::STREAM_ARGUMENT_VERSION)
+            .ok_or_else(|| CaatError::ProtocolError("the caller does not support streamed arguments".to_string()))?;
+        let mut request = JsonValue::new_object();
+        request["stream"] = self.id.into();
+        let mut connection = LocalSocketStream::connect(self.socket.as_str())
+            .map_err(|e| CaatError::ProtocolError(format!("failed to connect to caller: {}", e)))?;
+        frame::write_frame(&mut connection, version, ContentType::Json, FrameKind::StreamRequest, request.dump().as_bytes())
+            .map_err(|e| CaatError::ProtocolError(format!("failed to send stream request: {}", e)))?;
+        Ok(connection)
+    }
+
+    /// Reads the next value. Functions and streams in it are the caller's,
+    /// which is the caller of this process the stream came from.
+    fn fetch(&mut self) -> Result<Option<Value>, CaatError> {
+        let connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.connect()?,
+        };
+        let connection = self.connection.insert(connection);
+        match frame::read_message(connection)? {
+            Message::Frame(frame) if frame.kind == FrameKind::Yield => {
+                Codec::from_content_type(frame.content_type)?.decode_with(&frame.payload, None).map(Some)
+            }
+            Message::Frame(frame) if frame.kind == FrameKind::Return => Ok(None),
+            _ => Err(CaatError::ProtocolError("the stream was cut off".to_string())),
+        }
+    }
+}
+
+impl Iterator for RemoteStream {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        if self.done {
+            return None;
+        }
+        match self.fetch() {
+            Ok(Some(value)) => Some(value),
+            Ok(None) => {
+                self.done = true;
+                self.connection = None;
+                None
+            }
+            Err(error) => {
+                self.done = true;
+                self.connection = None;
+                Some(Value::from(error))
+            }
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Points and spans of time passed as values.
+//!
+//! A `Value::Timestamp` is an instant in UTC with nanosecond precision, written
+//! on the wire as an RFC 3339 string such as `2024-05-01T12:30:00.25Z`. A
+//! `Value::Duration` is a `std::time::Duration`, written as an ISO 8601
+//! duration in seconds such as `PT90.5S`.
+//!
+//! Both convert from and to `SystemTime` and `Duration`. With the `chrono`
+//! feature they also convert from and to `chrono::DateTime<Utc>` and
+//! `chrono::TimeDelta`, and with the `time` feature from and to
+//! `time::OffsetDateTime` and `time::Duration`. Negative durations from those
+//! crates cannot be values.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::Value;
+
+/// The earliest instant RFC 3339 can write, `0000-01-01T00:00:00Z`.
+const MIN_SECONDS: i64 = -62_167_219_200;
+/// The latest whole second RFC 3339 can write, `9999-12-31T23:59:59Z`.
+const MAX_SECONDS: i64 = 253_402_300_799;
+const NANOS_PER_SECOND: u32 = 1_000_000_000;
+
+/// An instant in UTC, between the years 0 and 9999.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    seconds: i64,
+    nanos: u32,
+}
+
+impl Timestamp {
+    /// The instant `seconds` and `nanos` after the Unix epoch, if it is in
+    /// range and `nanos` is less than a second.
+    pub fn from_unix(seconds: i64, nanos: u32) -> Option<Timestamp> {
+        if !(MIN_SECONDS..=MAX_SECONDS).contains(&seconds) || nanos >= NANOS_PER_SECOND {
+            return None;
+        }
+        Some(Timestamp { seconds, nanos })
+    }
+
+    pub fn now() -> Timestamp {
+        Timestamp::try_from(SystemTime::now()).expect("the clock is set between the years 0 and 9999")
+    }
+
+    /// Whole seconds since the Unix epoch, negative before it.
+    pub fn unix_seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    /// Nanoseconds past `unix_seconds`.
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+}
+
+impl TryFrom<SystemTime> for Timestamp {
+    type Error = &'static str;
+    fn try_from(time: SystemTime) -> Result<Self, Self::Error> {
+        let (seconds, nanos) = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => (i64::try_from(after.as_secs()).ok(), after.subsec_nanos()),
+            Err(before) => {
+                let before = before.duration();
+                let seconds = i64::try_from(before.as_secs()).ok().map(|s| -s);
+                match before.subsec_nanos() {
+                    0 => (seconds, 0),
+                    nanos => (seconds.and_then(|s| s.checked_sub(1)), NANOS_PER_SECOND - nanos),
+                }
+            }
+        };
+        seconds.and_then(|seconds| Timestamp::from_unix(seconds, nanos)).ok_or("time is out of range")
+    }
+}
+
+impl TryFrom<Timestamp> for SystemTime {
+    type Error = &'static str;
+    fn try_from(timestamp: Timestamp) -> Result<Self, Self::Error> {
+        let time = match u64::try_from(timestamp.seconds) {
+            Ok(seconds) => UNIX_EPOCH.checked_add(Duration::new(seconds, timestamp.nanos)),
+            Err(_) => UNIX_EPOCH.checked_sub(Duration::from_secs(timestamp.seconds.unsigned_abs()))
+                .and_then(|time| time.checked_add(Duration::from_nanos(timestamp.nanos.into()))),
+        };
+        time.ok_or("time is out of range")
+    }
+}
+
+/// Writes RFC 3339 in UTC, with as many digits of the fraction as it takes:
+/// none, 3, 6 or 9.
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = self.seconds.div_euclid(86_400);
+        let second_of_day = self.seconds.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+        write!(
+            f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year, month, day, second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60,
+        )?;
+        write_fraction(f, self.nanos)?;
+        write!(f, "Z")
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Reads RFC 3339 with any offset, converting it to UTC. Leap seconds are
+/// not accepted, and digits of the fraction past nanoseconds are dropped.
+impl FromStr for Timestamp {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Timestamp, &'static str> {
+        const MALFORMED: &str = "not an RFC 3339 timestamp";
+        let b = s.as_bytes();
+        if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b't' | b' ')
+            || b[13] != b':' || b[16] != b':'
+        {
+            return Err(MALFORMED);
+        }
+        let year = digits(&b[0..4]).ok_or(MALFORMED)?;
+        let month = digits(&b[5..7]).ok_or(MALFORMED)?;
+        let day = digits(&b[8..10]).ok_or(MALFORMED)?;
+        let hour = digits(&b[11..13]).ok_or(MALFORMED)?;
+        let minute = digits(&b[14..16]).ok_or(MALFORMED)?;
+        let second = digits(&b[17..19]).ok_or(MALFORMED)?;
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 59 {
+            return Err(MALFORMED);
+        }
+
+        let mut rest = &b[19..];
+        let mut nanos = 0;
+        if rest.first() == Some(&b'.') {
+            let count = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            if count == 0 {
+                return Err(MALFORMED);
+            }
+            let fraction = &rest[1..1 + count.min(9)];
+            nanos = digits(fraction).ok_or(MALFORMED)? as u32 * 10u32.pow(9 - fraction.len() as u32);
+            rest = &rest[1 + count..];
+        }
+        let offset = match rest {
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+                let hours = digits(&[*h1, *h2]).ok_or(MALFORMED)?;
+                let minutes = digits(&[*m1, *m2]).ok_or(MALFORMED)?;
+                if hours > 23 || minutes > 59 {
+                    return Err(MALFORMED);
+                }
+                let offset = hours * 3600 + minutes * 60;
+                if *sign == b'-' { -offset } else { offset }
+            }
+            _ => return Err(MALFORMED),
+        };
+        let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+        Timestamp::from_unix(seconds, nanos).ok_or("timestamp is out of range")
+    }
+}
+
+/// Writes a duration as ISO 8601 in seconds, such as `PT90.5S`.
+pub(crate) fn format_duration(f: &mut fmt::Formatter, duration: &Duration) -> fmt::Result {
+    write!(f, "PT{}", duration.as_secs())?;
+    write_fraction(f, duration.subsec_nanos())?;
+    write!(f, "S")
+}
+
+/// Reads an ISO 8601 duration made of days, hours, minutes and seconds, such
+/// as `P1DT2H` or `PT0.5S`. Years, months and weeks have no fixed length, so
+/// they are not accepted.
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let rest = s.strip_prefix('P')?;
+    let (date, time) = match rest.split_once('T') {
+        Some((date, time)) if !time.is_empty() => (date, Some(time)),
+        Some(_) => return None,
+        None => (rest, None),
+    };
+    let mut total = Duration::ZERO;
+    if !date.is_empty() {
+        let days = date.strip_suffix('D')?;
+        total += Duration::from_secs(days.parse::<u64>().ok()?.checked_mul(86_400)?);
+    }
+    let mut time = time.unwrap_or("");
+    for (unit, seconds) in [('H', 3600), ('M', 60)] {
+        if let Some(end) = time.find(unit) {
+            let count = time[..end].parse::<u64>().ok()?;
+            total = total.checked_add(Duration::from_secs(count.checked_mul(seconds)?))?;
+            time = &time[end + 1..];
+        }
+    }
+    if let Some(seconds) = time.strip_suffix('S') {
+        let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+        let mut nanos = 0;
+        if seconds.contains('.') {
+            let fraction = fraction.as_bytes();
+            if fraction.is_empty() || !fraction.iter().all(u8::is_ascii_digit) {
+                return None;
+            }
+            let fraction = &fraction[..fraction.len().min(9)];
+            nanos = digits(fraction)? as u32 * 10u32.pow(9 - fraction.len() as u32);
+        }
+        total = total.checked_add(Duration::new(whole.parse().ok()?, nanos))?;
+    } else if !time.is_empty() {
+        return None;
+    }
+    if date.is_empty() && s.len() <= 2 {
+        return None;
+    }
+    Some(total)
+}
+
+fn write_fraction(f: &mut fmt::Formatter, nanos: u32) -> fmt::Result {
+    match nanos {
+        0 => Ok(()),
+        _ if nanos.is_multiple_of(1_000_000) => write!(f, ".{:03}", nanos / 1_000_000),
+        _ if nanos.is_multiple_of(1000) => write!(f, ".{:06}", nanos / 1000),
+        _ => write!(f, ".{:09}", nanos),
+    }
+}
+
+/// Parses a run of ASCII digits.
+fn digits(bytes: &[u8]) -> Option<i64> {
+    if bytes.is_empty() || !bytes.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |n, digit| n * 10 + i64::from(digit - b'0')))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to a date of the proleptic Gregorian calendar, after
+/// Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_conversions {
+    use chrono::{DateTime, TimeDelta, Utc};
+    use super::Timestamp;
+    use crate::Value;
+
+    impl TryFrom<DateTime<Utc>> for Timestamp {
+        type Error = &'static str;
+        fn try_from(time: DateTime<Utc>) -> Result<Self, Self::Error> {
+            Timestamp::from_unix(time.timestamp(), time.timestamp_subsec_nanos()).ok_or("time is out of range")
+        }
+    }
+
+    impl From<Timestamp> for DateTime<Utc> {
+        fn from(timestamp: Timestamp) -> Self {
+            DateTime::from_timestamp(timestamp.seconds, timestamp.nanos)
+                .expect("chrono covers the years 0 to 9999")
+        }
+    }
+
+    impl TryFrom<DateTime<Utc>> for Value {
+        type Error = &'static str;
+        fn try_from(time: DateTime<Utc>) -> Result<Self, Self::Error> {
+            Timestamp::try_from(time).map(Value::Timestamp)
+        }
+    }
+
+    impl TryFrom<Value> for DateTime<Utc> {
+        type Error = &'static str;
+        fn try_from(value: Value) -> Result<Self, Self::Error> {
+            Timestamp::try_from(value).map(DateTime::from)
+        }
+    }
+
+    impl TryFrom<TimeDelta> for Value {
+        type Error = &'static str;
+        fn try_from(delta: TimeDelta) -> Result<Self, Self::Error> {
+            delta.to_std().map(Value::Duration).map_err(|_| "duration is negative")
+        }
+    }
+
+    impl TryFrom<Value> for TimeDelta {
+        type Error = &'static str;
+        fn try_from(value: Value) -> Result<Self, Self::Error> {
+            TimeDelta::from_std(std::time::Duration::try_from(value)?).map_err(|_| "Value is out of range")
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_conversions {
+    use time::OffsetDateTime;
+    use super::Timestamp;
+    use crate::Value;
+
+    impl TryFrom<OffsetDateTime> for Timestamp {
+        type Error = &'static str;
+        fn try_from(time: OffsetDateTime) -> Result<Self, Self::Error> {
+            Timestamp::from_unix(time.unix_timestamp(), time.nanosecond()).ok_or("time is out of range")
+        }
+    }
+
+    impl From<Timestamp> for OffsetDateTime {
+        fn from(timestamp: Timestamp) -> Self {
+            let nanos = i128::from(timestamp.seconds) * 1_000_000_000 + i128::from(timestamp.nanos);
+            OffsetDateTime::from_unix_timestamp_nanos(nanos).expect("time covers the years 0 to 9999")
+        }
+    }
+
+    impl TryFrom<OffsetDateTime> for Value {
+        type Error = &'static str;
+        fn try_from(time: OffsetDateTime) -> Result<Self, Self::Error> {
+            Timestamp::try_from(time).map(Value::Timestamp)
+        }
+    }
+
+    impl TryFrom<Value> for OffsetDateTime {
+        type Error = &'static str;
+        fn try_from(value: Value) -> Result<Self, Self::Error> {
+            Timestamp::try_from(value).map(OffsetDateTime::from)
+        }
+    }
+
+    impl TryFrom<time::Duration> for Value {
+        type Error = &'static str;
+        fn try_from(duration: time::Duration) -> Result<Self, Self::Error> {
+            std::time::Duration::try_from(duration).map(Value::Duration).map_err(|_| "duration is negative")
+        }
+    }
+
+    impl TryFrom<Value> for time::Duration {
+        type Error = &'static str;
+        fn try_from(value: Value) -> Result<Self, Self::Error> {
+            time::Duration::try_from(std::time::Duration::try_from(value)?).map_err(|_| "Value is out of range")
+        }
+    }
+}
+
+impl From<Timestamp> for Value {
+    fn from(timestamp: Timestamp) -> Self {
+        Value::Timestamp(timestamp)
+    }
+}
+
+impl TryFrom<Value> for Timestamp {
+    type Error = &'static str;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Timestamp(timestamp) => Ok(timestamp),
+            _ => Err("Value is not a timestamp"),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Foreign functions with typed arguments and results.
+
+use std::fmt;
+use std::marker::PhantomData;
+use crate::{CaatError, CallOptions, ForeignFunction, Value};
+
+/// Argument lists that can be passed to a `TypedForeignFunction`: tuples of up
+/// to twelve `Into<Value>` types, and `()` for no arguments.
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Value>;
+}
+
+impl IntoArgs for () {
+    fn into_args(self) -> Vec<Value> {
+        Vec::new()
+    }
+}
+
+macro_rules! tuple_into_args {
+    ($($name:ident)+) => {
+        impl<$($name: Into<Value>),+> IntoArgs for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<Value> {
+                let ($($name,)+) = self;
+                vec![$($name.into()),+]
+            }
+        }
+    };
+}
+
+tuple_into_args!(A);
+tuple_into_args!(A B);
+tuple_into_args!(A B C);
+tuple_into_args!(A B C D);
+tuple_into_args!(A B C D E);
+tuple_into_args!(A B C D E F);
+tuple_into_args!(A B C D E F G);
+tuple_into_args!(A B C D E F G H);
+tuple_into_args!(A B C D E F G H I);
+tuple_into_args!(A B C D E F G H I J);
+tuple_into_args!(A B C D E F G H I J K);
+tuple_into_args!(A B C D E F G H I J K L);
+
+/// A `ForeignFunction` that takes the arguments `A` and returns an `R`.
+///
+/// The types are not checked against the command, which may be written in
+/// any language. A return value that does not convert to `R` is reported as
+/// `CaatError::DecodeError`.
+///
+/// ```no_run
+/// use caat_rust::TypedForeignFunction;
+///
+/// let greet = TypedForeignFunction::<(String, i64), Vec<String>>::new("greet");
+/// let greetings = greet.call(("Ada".to_string(), 2)).unwrap();
+/// ```
+pub struct TypedForeignFunction<A, R> {
+    function: ForeignFunction,
+    types: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R> TypedForeignFunction<A, R>
+where
+    A: IntoArgs,
+    R: TryFrom<Value>,
+    R::Error: fmt::Display,
+{
+    pub fn new<S>(name: &S) -> TypedForeignFunction<A, R>
+    where S: AsRef<str> + ?Sized {
+        TypedForeignFunction::from(ForeignFunction::new(name))
+    }
+
+    pub fn call(&self, args: A) -> Result<R, CaatError> {
+        self.call_with(args, &CallOptions::default())
+    }
+
+    pub fn call_with(&self, args: A, options: &CallOptions) -> Result<R, CaatError> {
+        let value = self.function.call_with(&args.into_args(), options)?;
+        R::try_from(value).map_err(|e| {
+            CaatError::DecodeError(format!("expected {}: {}", std::any::type_name::<R>(), e))
+        })
+    }
+}
+
+impl<A, R> TypedForeignFunction<A, R> {
+    /// The untyped function this calls.
+    pub fn function(&self) -> &ForeignFunction {
+        &self.function
+    }
+}
+
+impl<A, R> From<ForeignFunction> for TypedForeignFunction<A, R> {
+    fn from(function: ForeignFunction) -> Self {
+        TypedForeignFunction { function, types: PhantomData }
+    }
+}
+
+impl<A, R> Clone for TypedForeignFunction<A, R> {
+    fn clone(&self) -> Self {
+        TypedForeignFunction::from(self.function.clone())
+    }
+}
+
+impl<A, R> fmt::Display for TypedForeignFunction<A, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.function)
+    }
+}
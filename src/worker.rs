@@ -0,0 +1,340 @@
+//! Callees that stay alive between calls.
+//!
+//! Starting a process for every call is simple but slow when the callee has
+//! expensive setup. A callee built around `serve_loop` can instead be started
+//! once with `ForeignFunction::spawn_worker`. It is started with `CAAT_WORKER`
+//! set, connects to its socket, sends a `Ready` frame, and then answers `Call`
+//! frames on that connection one at a time until it is shut down.
+//!
+//! A worker that does not connect within `START_TIMEOUT`, or the timeout of
+//! the call it is started for, is terminated and the call fails with
+//! `CaatError::Timeout`, so a command that is not a worker at all cannot hang
+//! its caller.
+//!
+//! A worker that dies is started again on the next call. The call that was
+//! running when it died fails rather than being retried, since it may already
+//! have had side effects. The same goes for a worker stopped because a call
+//! timed out or was cancelled, see `Worker::call_with`.
+
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use crate::callback::{Callbacks, Nesting};
+use crate::codec::{self, Codec};
+use crate::frame::{self, ContentType, FrameKind, Message};
+use crate::process::{self, ChildWatch, Event, StderrCapture};
+use crate::socket::{self, SocketFile};
+use crate::{Caat, CaatError, CallOptions, ForeignFunction, Value};
+
+pub(crate) const WORKER_VAR: &str = "CAAT_WORKER";
+/// How long a worker gets to exit on its own before it is terminated.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+/// How long a worker gets to connect and say it is ready, unless the call it
+/// is started for has a timeout.
+pub const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A command kept running to answer calls, see the module documentation.
+///
+/// Calls from several threads are answered one after the other. Dropping the
+/// worker shuts it down.
+pub struct Worker {
+    function: ForeignFunction,
+    running: Mutex<Option<Running>>,
+    starts: AtomicUsize,
+}
+
+impl Worker {
+    /// Starts `function` as a worker and waits until it is ready.
+    pub(crate) fn start(function: ForeignFunction, options: &CallOptions) -> Result<Worker, CaatError> {
+        let running = Running::start(&function, options)?;
+        Ok(Worker { function, running: Mutex::new(Some(running)), starts: AtomicUsize::new(1) })
+    }
+
+    /// The command the worker runs.
+    pub fn function(&self) -> &ForeignFunction {
+        &self.function
+    }
+
+    /// The process id of the worker, if it is running.
+    pub fn pid(&self) -> Option<u32> {
+        let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        running.as_ref().filter(|running| !running.exited).map(|running| running.pid)
+    }
+
+    /// How many times the worker had to be started again after it died.
+    pub fn restarts(&self) -> usize {
+        self.starts.load(Ordering::Relaxed).saturating_sub(1)
+    }
+
+    /// Asks the worker to exit and waits for it, terminating it if it does
+    /// not exit within a second.
+    ///
+    /// Returns an error if the worker had to be terminated or exited with a
+    /// non-zero status.
+    pub fn shutdown(self) -> Result<(), CaatError> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        match running.take() {
+            Some(mut running) => running.stop(),
+            None => Ok(()),
+        }
+    }
+
+    /// Calls the worker with the given options.
+    ///
+    /// The timeout counts from when the call is sent, after any calls from
+    /// other threads ahead of it are done. When it expires or the cancellation
+    /// token is triggered the worker is stopped, given `grace_period` to exit
+    /// after `SIGTERM`, and `CaatError::Timeout` or `CaatError::Cancelled` is
+    /// returned. The worker is stopped even when `kill_on_timeout` is off,
+    /// since a late answer would be taken for that of the next call, and it is
+    /// started again on the next call. A worker that has to be started again
+    /// gets the same timeout to become ready. `max_depth` only applies when
+    /// the worker is started.
+    pub fn call_with(&self, args: &[Value], options: &CallOptions) -> Result<Value, CaatError> {
+        if let Some(error) = ForeignFunction::interrupted(options) {
+            return Err(error);
+        }
+        self.function.check_args(args)?;
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        if running.as_mut().is_some_and(|worker| !worker.alive()) {
+            *running = None;
+        }
+        let worker = match running.take() {
+            Some(worker) => running.insert(worker),
+            None => {
+                self.starts.fetch_add(1, Ordering::Relaxed);
+                running.insert(Running::start(&self.function, options)?)
+            }
+        };
+        worker.call(&self.function, args, options)
+    }
+}
+
+impl Caat for Worker {
+    fn call(&self, args: &[Value]) -> Value {
+        self.try_call(args).unwrap_or_else(Value::from)
+    }
+
+    fn try_call(&self, args: &[Value]) -> Result<Value, CaatError> {
+        self.call_with(args, &CallOptions::default())
+    }
+}
+
+impl fmt::Display for Worker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.function)
+    }
+}
+
+/// One running worker process.
+struct Running {
+    control: LocalSocketStream,
+    version: u8,
+    /// The codec the worker answers in, which calls are sent in too.
+    codec: Codec,
+    pid: u32,
+    /// The callbacks of the call in progress, shared with the accept thread.
+    callbacks: Arc<Mutex<Arc<Callbacks>>>,
+    stderr: Option<StderrCapture>,
+    exited: bool,
+    child: ChildWatch,
+    events: Receiver<Event>,
+    _socket_file: SocketFile,
+}
+
+impl Running {
+    /// Starts a worker process and waits for its `Ready` frame, for at most
+    /// `options.timeout` or else `START_TIMEOUT`.
+    fn start(function: &ForeignFunction, options: &CallOptions) -> Result<Running, CaatError> {
+        let nesting = Nesting::child(options.max_depth)?;
+        let timeout = options.timeout.unwrap_or(START_TIMEOUT);
+        let deadline = Instant::now() + timeout;
+        let socket_path = socket::socket_path().map_err(CaatError::SocketBind)?;
+        let (mut command, _) = function.command(&[], &socket_path, nesting);
+        command.env(WORKER_VAR, "1");
+
+        let listener = LocalSocketListener::bind(socket_path.as_str()).map_err(CaatError::SocketBind)?;
+        let socket_file = SocketFile(socket_path);
+        let mut handle = command.spawn().map_err(CaatError::SpawnFailed)?;
+        let pid = handle.id();
+        let stderr = StderrCapture::start(&mut handle);
+        let (sender, events) = mpsc::channel();
+        let child = ChildWatch::start(handle, sender.clone());
+        let callbacks = Arc::new(Mutex::new(Arc::new(Callbacks::default())));
+        socket::serve_worker(listener, sender, callbacks.clone());
+
+        let not_a_worker = || CaatError::ProtocolError(format!("`{}` does not support worker mode", function));
+        loop {
+            match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Event::Connected(control, ready)) => {
+                    return Ok(Running {
+                        control,
+                        version: ready.version,
+                        codec: Codec::from_content_type(ready.content_type).unwrap_or_default(),
+                        pid,
+                        callbacks,
+                        stderr: Some(stderr),
+                        exited: false,
+                        child,
+                        events,
+                        _socket_file: socket_file,
+                    });
+                }
+                Ok(Event::Exited(Ok(status))) => {
+                    process::exit_result(status, stderr)?;
+                    return Err(not_a_worker());
+                }
+                Ok(Event::Exited(Err(e))) => {
+                    return Err(CaatError::ProtocolError(format!("failed to wait for command: {}", e)));
+                }
+                Ok(Event::Returned(_)) => {
+                    child.terminate(&events, SHUTDOWN_GRACE);
+                    return Err(not_a_worker());
+                }
+                Ok(Event::AcceptFailed(e)) => {
+                    child.terminate(&events, SHUTDOWN_GRACE);
+                    return Err(CaatError::Accept(e));
+                }
+                Ok(Event::Cancelled) => (),
+                Err(RecvTimeoutError::Timeout) => {
+                    child.terminate(&events, options.grace_period);
+                    return Err(CaatError::Timeout(timeout));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(CaatError::ProtocolError("lost track of the command".to_string()));
+                }
+            }
+        }
+    }
+
+    /// Whether the worker is still running, as far as we know.
+    fn alive(&mut self) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            if let Event::Exited(_) = event {
+                self.exited = true;
+            }
+        }
+        !self.exited
+    }
+
+    fn call(&mut self, function: &ForeignFunction, args: &[Value], options: &CallOptions) -> Result<Value, CaatError> {
+        let callbacks = Callbacks::default();
+        let payload = function.encode_args(args, self.codec, &callbacks);
+        let callbacks = Arc::new(callbacks);
+        *self.callbacks.lock().unwrap_or_else(|e| e.into_inner()) = callbacks.clone();
+
+        let answer = if options.timeout.is_none() && options.cancellation.is_none() {
+            exchange(&mut self.control, self.version, self.codec, &payload)
+        } else {
+            self.exchange_until(&payload, options)?
+        };
+        match answer {
+            Some(Message::Frame(frame)) if frame.kind == FrameKind::Return => {
+                codec::decode_frame(&frame, Some(&callbacks))
+            }
+            _ => Err(self.lost()),
+        }
+    }
+
+    /// Sends a call and reads the answer on a thread of its own, stopping the
+    /// worker if the call times out or is cancelled first. Stopping it closes
+    /// the connection, which ends the read.
+    fn exchange_until(&mut self, payload: &[u8], options: &CallOptions) -> Result<Option<Message>, CaatError> {
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        let (version, codec) = (self.version, self.codec);
+        let control = &mut self.control;
+        std::thread::scope(|scope| {
+            let (sender, answers) = mpsc::channel();
+            let _registration = options.cancellation.as_ref().map(|token| {
+                let sender = sender.clone();
+                token.register(move || {
+                    let _ = sender.send(None);
+                })
+            });
+            scope.spawn(move || {
+                let _ = sender.send(Some(exchange(control, version, codec, payload)));
+            });
+            let received = match deadline {
+                Some(deadline) => answers.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok(),
+                None => answers.recv().ok(),
+            };
+            let error = match received {
+                Some(Some(answer)) => return Ok(answer),
+                Some(None) => CaatError::Cancelled,
+                None => CaatError::Timeout(options.timeout.unwrap_or_default()),
+            };
+            self.exited = true;
+            self.child.terminate(&self.events, options.grace_period);
+            Err(error)
+        })
+    }
+
+    /// Explains why the worker stopped answering, stopping it if it is still
+    /// around.
+    fn lost(&mut self) -> CaatError {
+        self.exited = true;
+        match wait_exit(&self.events, SHUTDOWN_GRACE) {
+            Some(Ok(status)) => {
+                let stderr = self.stderr.take().unwrap_or_else(|| StderrCapture::from_reader(None::<io::Empty>));
+                match process::exit_result(status, stderr) {
+                    Err(error) => error,
+                    Ok(()) => CaatError::ProtocolError("the worker exited during a call".to_string()),
+                }
+            }
+            Some(Err(e)) => CaatError::ProtocolError(format!("failed to wait for command: {}", e)),
+            None => {
+                self.child.terminate(&self.events, SHUTDOWN_GRACE);
+                CaatError::ProtocolError("the worker stopped answering".to_string())
+            }
+        }
+    }
+
+    /// Asks the worker to exit, terminating it if it does not.
+    fn stop(&mut self) -> Result<(), CaatError> {
+        if !self.alive() {
+            return Ok(());
+        }
+        self.exited = true;
+        let _ = frame::write_frame(&mut self.control, self.version, ContentType::Json, FrameKind::Shutdown, &[]);
+        match wait_exit(&self.events, SHUTDOWN_GRACE) {
+            Some(Ok(status)) => match self.stderr.take() {
+                Some(stderr) => process::exit_result(status, stderr),
+                None => Ok(()),
+            },
+            Some(Err(e)) => Err(CaatError::ProtocolError(format!("failed to wait for command: {}", e))),
+            None => {
+                self.child.terminate(&self.events, SHUTDOWN_GRACE);
+                Err(CaatError::ProtocolError("the worker did not exit when asked to shut down".to_string()))
+            }
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Sends a call to a worker and reads its answer, if it gives one.
+fn exchange(control: &mut LocalSocketStream, version: u8, codec: Codec, payload: &[u8]) -> Option<Message> {
+    frame::write_frame(control, version, codec.content_type(), FrameKind::Call, payload).ok()?;
+    frame::read_message(control).ok()
+}
+
+/// Waits up to `wait` for the worker to exit, dropping other events.
+fn wait_exit(events: &Receiver<Event>, wait: Duration) -> Option<io::Result<ExitStatus>> {
+    let deadline = Instant::now() + wait;
+    loop {
+        match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Event::Exited(status)) => return Some(status),
+            Ok(_) => continue,
+            Err(_) => return None,
+        }
+    }
+}
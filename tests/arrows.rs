@@ -0,0 +1,89 @@
+mod common;
+
+use caat_rust::{Caat, CaatExt, ForeignFunction, Value};
+
+fn echo() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("echo").to_str().unwrap())
+}
+
+fn list(values: Vec<Value>) -> Value {
+    Value::from(values)
+}
+
+#[test]
+fn then_feeds_the_result_forward() {
+    let pipeline = echo().then(echo());
+    let result = pipeline.call(&[Value::Integer(1), Value::Integer(2)]);
+    assert_eq!(result, list(vec![list(vec![Value::Integer(1), Value::Integer(2)])]));
+}
+
+#[test]
+fn first_and_second_touch_one_side_of_a_pair() {
+    let a = Value::from("a");
+    let b = Value::from("b");
+    assert_eq!(echo().first().call(&[a.clone(), b.clone()]), list(vec![list(vec![a.clone()]), b.clone()]));
+    assert_eq!(echo().second().call(&[list(vec![a.clone(), b.clone()])]), list(vec![a, list(vec![b])]));
+}
+
+#[test]
+fn split_and_fanout_build_pairs() {
+    let length = |value: Value| match value {
+        Value::List(l) => Value::Integer(l.len() as i64),
+        other => other,
+    };
+    let split = echo().split(echo().map_value(length));
+    let result = split.call(&[Value::Integer(1), Value::Integer(2)]);
+    assert_eq!(result, list(vec![list(vec![Value::Integer(1)]), Value::Integer(1)]));
+
+    let fanout = echo().fanout(echo().map_value(length));
+    let result = fanout.call(&[Value::Null, Value::Null, Value::Null]);
+    assert_eq!(result, list(vec![list(vec![Value::Null; 3]), Value::Integer(3)]));
+}
+
+#[test]
+fn failures_stop_the_pipeline() {
+    let failing = echo().map_value(|_| Value::Failure("stop".to_string()));
+    let pipeline = failing.then(echo().map_value(|_| panic!("ran after a failure")));
+    assert_eq!(pipeline.call(&[]), Value::Failure("stop".to_string()));
+    assert!(matches!(echo().first().call(&[Value::Null]), Value::Failure(_)));
+
+    let untouched = echo().map_value(|_| panic!("ran on the second element of a failed pair")).second();
+    let failed = [Value::Failure("stop".to_string()), Value::Null];
+    assert_eq!(untouched.call(&failed), Value::Failure("stop".to_string()));
+    assert!(untouched.try_call(&failed).is_err());
+}
+
+#[test]
+fn pipelines_are_values() {
+    let value = echo().then(echo()).into_value();
+    let function = match value {
+        Value::CAATFunction(function) => function,
+        other => panic!("expected a function, got {:?}", other),
+    };
+    assert!(function.to_string().contains(" >>> "));
+    let doubled = function.clone().fanout(function);
+    let result = doubled.call(&[Value::Boolean(true)]);
+    let once = list(vec![list(vec![Value::Boolean(true)])]);
+    assert_eq!(result, list(vec![once.clone(), once]));
+}
+
+#[test]
+fn try_call_keeps_the_error_of_a_failed_step() {
+    use std::sync::Arc;
+    use caat_rust::CaatError;
+
+    let missing = || ForeignFunction::new("caat-test-no-such-command");
+    let shared: Arc<dyn Caat + Send + Sync> = Arc::new(missing());
+    assert!(matches!(shared.try_call(&[]), Err(CaatError::SpawnFailed(_))));
+
+    let pair = [Value::Integer(1), Value::Integer(2)];
+    assert!(matches!(missing().then(echo()).try_call(&[]), Err(CaatError::SpawnFailed(_))));
+    assert!(matches!(echo().compose(missing()).try_call(&[]), Err(CaatError::SpawnFailed(_))));
+    assert!(matches!(echo().split(missing()).try_call(&pair), Err(CaatError::SpawnFailed(_))));
+    assert!(matches!(echo().fanout(missing()).try_call(&[]), Err(CaatError::SpawnFailed(_))));
+    assert!(matches!(missing().first().try_call(&pair), Err(CaatError::SpawnFailed(_))));
+    assert!(matches!(Arc::new(echo()).then(shared).try_call(&[]), Err(CaatError::SpawnFailed(_))));
+
+    let result = echo().split(echo()).try_call(&pair).unwrap();
+    assert_eq!(result, list(vec![list(vec![Value::Integer(1)]), list(vec![Value::Integer(2)])]));
+}
@@ -0,0 +1,81 @@
+#![cfg(all(feature = "tokio", unix))]
+
+mod common;
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use caat_rust::{AsyncCaat, ForeignFunction, Value};
+
+#[tokio::test]
+async fn async_call_returns_value() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    let result = AsyncCaat::call(&echo, &[Value::from("hello"), Value::Integer(2)]).await;
+    assert_eq!(result.unwrap(), Value::from(vec![Value::from("hello"), Value::Integer(2)]));
+}
+
+#[tokio::test]
+async fn dropping_the_call_kills_the_callee_and_removes_the_socket() {
+    let report = std::env::temp_dir().join(format!("caat-async-sleep-{}", std::process::id()));
+    let _ = std::fs::remove_file(&report);
+    let sleep = ForeignFunction::new(common::fixture("sleep").to_str().unwrap());
+    let args = [Value::from("10000"), Value::from(report.to_str().unwrap())];
+    let started = async {
+        while std::fs::read_to_string(&report).map(|r| r.lines().count() < 2).unwrap_or(true) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::select! {
+        result = AsyncCaat::call(&sleep, &args) => panic!("the call returned {:?}", result),
+        _ = started => (),
+    }
+
+    let contents = std::fs::read_to_string(&report).unwrap();
+    let _ = std::fs::remove_file(&report);
+    let mut lines = contents.lines();
+    let pid = lines.next().unwrap().parse::<libc::pid_t>().unwrap();
+    let socket = PathBuf::from(lines.next().unwrap());
+    assert!(!socket.exists(), "{} was left behind", socket.display());
+    // Tokio reaps killed children in the background, so give it a moment.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while unsafe { libc::kill(pid, 0) } == 0 {
+        assert!(Instant::now() < deadline, "process {} is still around", pid);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn async_calls_serve_callbacks() {
+    use caat_rust::{CaatExt, NativeFunction};
+
+    let apply = ForeignFunction::new(common::fixture("apply").to_str().unwrap());
+    let negate = NativeFunction::new("negate", |args| match args {
+        [Value::Boolean(b)] => Value::Boolean(!b),
+        _ => Value::Null,
+    });
+    let result = AsyncCaat::call(&apply, &[negate.into_value(), Value::Boolean(true)]).await;
+    assert_eq!(result.unwrap(), Value::from(vec![false]));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn reading_stderr_does_not_block_the_runtime() {
+    use std::sync::{Arc, Mutex};
+    use caat_rust::CaatError;
+
+    // The shell exits at once, but the background sleep keeps its stderr
+    // open, so the caller waits a while for the pipe before giving up.
+    let shell = ForeignFunction::new("sh -c");
+    let last_tick = Arc::new(Mutex::new(Instant::now()));
+    let ticks = last_tick.clone();
+    let ticker = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            *ticks.lock().unwrap() = Instant::now();
+        }
+    });
+    let result = AsyncCaat::call(&shell, &[Value::from("sleep 2 & exit 3")]).await;
+    let returned = Instant::now();
+    ticker.abort();
+    assert!(matches!(result, Err(CaatError::NonZeroExit { code: Some(3), .. })), "{:?}", result);
+    let idle = returned - *last_tick.lock().unwrap();
+    assert!(idle < Duration::from_millis(50), "the runtime stood still for {:?}", idle);
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use caat_rust::{BigInt, Caat, Codec, Decimal, ForeignFunction, Value};
+
+#[test]
+fn wide_integers_do_not_wrap() {
+    assert_eq!(Value::from(u64::MAX), Value::BigInt(BigInt::from(u64::MAX)));
+    assert_eq!(Value::from(i64::MAX as u64), Value::Integer(i64::MAX));
+    assert_eq!(Value::from(u128::MAX), Value::BigInt(BigInt::from(u128::MAX)));
+    assert_eq!(Value::from(-5i128), Value::Integer(-5));
+}
+
+#[test]
+fn narrowing_conversions_are_checked() {
+    assert_eq!(u64::try_from(Value::from(u64::MAX)), Ok(u64::MAX));
+    assert_eq!(i128::try_from(Value::from(i128::MIN)), Ok(i128::MIN));
+    assert_eq!(u128::try_from(Value::Integer(7)), Ok(7));
+    assert!(i64::try_from(Value::from(u64::MAX)).is_err());
+    assert!(u64::try_from(Value::Integer(-1)).is_err());
+    assert!(u128::try_from(Value::from(-1i128)).is_err());
+    assert_eq!(BigInt::try_from(Value::Integer(3)), Ok(BigInt::from(3)));
+}
+
+#[test]
+fn big_numbers_are_strings_on_the_wire() {
+    let big = Value::from(u128::MAX);
+    assert_eq!(big.to_json(), r#"{"type":"BigInt","value":"340282366920938463463374607431768211455"}"#);
+    let price = Value::from("19.90".parse::<Decimal>().unwrap());
+    assert_eq!(price.to_json(), r#"{"type":"Decimal","value":"19.90"}"#);
+    for value in [big, price] {
+        for codec in Codec::AVAILABLE {
+            assert_eq!(codec.decode(&codec.encode(&value)).unwrap(), value, "{}", codec);
+        }
+    }
+}
+
+#[test]
+fn small_big_numbers_decode_as_integers() {
+    let small = Value::BigInt(BigInt::from(-42));
+    assert_eq!(Value::from_json(&small.to_json()), Some(Value::Integer(-42)));
+    for codec in Codec::AVAILABLE {
+        assert_eq!(codec.decode(&codec.encode(&small)).unwrap(), Value::Integer(-42), "{}", codec);
+    }
+    let edge = Value::BigInt(BigInt::from(i64::MIN));
+    assert_eq!(Value::from_json(&edge.to_json()), Some(Value::Integer(i64::MIN)));
+    let beyond = Value::BigInt(BigInt::from(i64::MIN) - 1);
+    assert_eq!(Value::from_json(&beyond.to_json()), Some(beyond));
+}
+
+#[test]
+fn decimals_keep_their_scale() {
+    let price = Value::from(Decimal::new(1990, 2));
+    assert_eq!(price.to_string(), "19.90");
+    assert_eq!(Decimal::try_from(price), Ok(Decimal::new(1990, 2)));
+    assert_eq!(Decimal::try_from(Value::Integer(4)), Ok(Decimal::from(4)));
+    assert!(Decimal::try_from(Value::Float(0.1)).is_err());
+}
+
+#[test]
+fn big_numbers_pass_through_a_call() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    let args = [Value::from(u128::MAX), Value::from(Decimal::new(-5, 3))];
+    assert_eq!(echo.try_call(&args).unwrap(), Value::from(args.to_vec()));
+}
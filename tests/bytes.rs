@@ -0,0 +1,66 @@
+mod common;
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use caat_rust::{Caat, ForeignFunction, Value};
+
+fn echo() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("echo").to_str().unwrap())
+}
+
+#[test]
+fn bytes_are_base64_in_json() {
+    let value = Value::from(vec![0u8, 159, 146, 150, 255]);
+    assert_eq!(value.to_json(), r#"{"type":"Bytes","value":"AJ+Slv8="}"#);
+    assert_eq!(Value::from_json(&value.to_json()), Some(value));
+}
+
+#[test]
+fn bytes_reach_the_callee_unchanged() {
+    let bytes = vec![0u8, 1, 0xc3, 0x28, 0xff, b'\n'];
+    let result = echo().try_call(&[Value::from(bytes.clone())]).unwrap();
+    assert_eq!(result, Value::from(vec![Value::Bytes(bytes)]));
+}
+
+#[test]
+fn vectors_of_bytes_convert_back() {
+    let bytes = Vec::<u8>::try_from(Value::from(&b"abc"[..])).unwrap();
+    assert_eq!(bytes, b"abc");
+    assert_eq!(Vec::<u8>::try_from(Value::from("abc")).unwrap(), b"abc");
+    assert!(Vec::<u8>::try_from(Value::Integer(1)).is_err());
+}
+
+#[test]
+fn utf8_paths_are_strings() {
+    let path = PathBuf::from("/tmp/notes.txt");
+    assert_eq!(Value::from(path.clone()), Value::from("/tmp/notes.txt"));
+    assert_eq!(PathBuf::try_from(Value::from(path.clone())).unwrap(), path);
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_os_strings_survive_a_round_trip() {
+    use std::os::unix::ffi::OsStringExt;
+
+    let name = OsString::from_vec(vec![b'a', 0xff, b'b']);
+    let value = Value::from(name.clone());
+    assert_eq!(value, Value::Bytes(vec![b'a', 0xff, b'b']));
+    assert_eq!(OsString::try_from(value.clone()).unwrap(), name);
+    let echoed = echo().try_call(&[value]).unwrap();
+    assert_eq!(echoed, Value::from(vec![Value::Bytes(vec![b'a', 0xff, b'b'])]));
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_arguments_from_a_shell_are_bytes() {
+    use std::os::unix::ffi::OsStringExt;
+
+    let output = std::process::Command::new(common::fixture("echo"))
+        .arg(OsString::from_vec(vec![0xfe, b'x']))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // Started from a shell, the callee prints its result, escaping the bytes.
+    let printed = String::from_utf8(output.stdout).unwrap();
+    assert!(printed.contains(r"\xfex"), "{}", printed);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use caat_rust::{Caat, CaatError, CaatExt, CallOptions, ForeignFunction, NativeFunction, Value};
+
+fn apply() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("apply").to_str().unwrap())
+}
+
+fn double() -> Value {
+    NativeFunction::new("double", |args| match args {
+        [Value::Integer(i)] => Value::Integer(i * 2),
+        _ => Value::Failure("double takes one integer".to_string()),
+    }).into_value()
+}
+
+#[test]
+fn callees_call_back_into_native_functions() {
+    let result = apply().try_call(&[double(), Value::Integer(1), Value::Integer(2), Value::Integer(3)]);
+    assert_eq!(result.unwrap(), Value::from(vec![2, 4, 6]));
+}
+
+#[test]
+fn callback_failures_reach_the_callee() {
+    let result = apply().try_call(&[double(), Value::from("x")]).unwrap();
+    assert_eq!(result, Value::from(vec![Value::Failure("double takes one integer".to_string())]));
+}
+
+#[test]
+fn callbacks_run_in_the_caller() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let count = NativeFunction::new("count", move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Value::Null
+    });
+    apply().try_call(&[count.into_value(), Value::Null, Value::Null]).unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn callbacks_can_start_calls() {
+    let nested = NativeFunction::new("nested", |args| apply().call(&[double(), args[0].clone()]));
+    let result = apply().try_call(&[nested.into_value(), Value::Integer(5)]).unwrap();
+    assert_eq!(result, Value::from(vec![Value::from(vec![10])]));
+}
+
+#[test]
+fn recursion_is_limited() {
+    fn recurse() -> Value {
+        NativeFunction::new("recurse", |args| apply().call(&[recurse(), args[0].clone()])).into_value()
+    }
+    let options = CallOptions::new().max_depth(3);
+    let result = apply().call_with(&[recurse(), Value::Null], &options).unwrap();
+    let mut innermost = result;
+    loop {
+        innermost = match innermost {
+            Value::List(l) if l.len() == 1 => l[0].clone(),
+            other => break assert_eq!(other, Value::from(CaatError::DepthExceeded(3))),
+        };
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn foreign_functions_are_passed_as_plain_commands() {
+    // The script records its parent, which is the callee when it is run from
+    // there and this process when it comes back as a callback.
+    let script = std::env::temp_dir().join(format!("caat-parent-{}.sh", std::process::id()));
+    let report = script.with_extension("sh.ppid");
+    std::fs::write(&script, "echo $PPID > \"$0.ppid\"\n").unwrap();
+    let command = ForeignFunction::new(&format!("sh {}", script.display()));
+    apply().try_call(&[Value::CAATFunction(Arc::new(command)), Value::Null]).unwrap();
+    let parent = std::fs::read_to_string(&report).unwrap();
+    let _ = std::fs::remove_file(&script);
+    let _ = std::fs::remove_file(&report);
+    assert_ne!(parent.trim().parse::<u32>().unwrap(), std::process::id());
+}
+
+#[test]
+fn callbacks_pass_through_callbacks() {
+    let call_with_21 = NativeFunction::new("call_with_21", |args| match args {
+        [Value::CAATFunction(function)] => function.call(&[Value::Integer(21)]),
+        _ => Value::Failure("call_with_21 takes one function".to_string()),
+    });
+    let result = apply().try_call(&[call_with_21.into_value(), double()]).unwrap();
+    assert_eq!(result, Value::from(vec![42]));
+
+    let make_double = NativeFunction::new("make_double", |_| double());
+    let result = apply().try_call(&[make_double.into_value(), Value::Null]).unwrap();
+    match &result[0] {
+        Value::CAATFunction(function) => assert_eq!(function.call(&[Value::Integer(4)]), Value::Integer(8)),
+        other => panic!("expected a function, got {:?}", other),
+    }
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "macros")]
+
+mod common;
+
+use std::process::Command;
+use caat_rust::{CaatError, Caat, ForeignFunction, TypedForeignFunction, Value};
+
+fn greet() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("greet").to_str().unwrap())
+}
+
+#[test]
+fn typed_parameters_are_decoded() {
+    let result = greet().try_call(&[Value::from("Ada"), Value::Integer(2)]).unwrap();
+    assert_eq!(result, Value::from(vec!["Hello, Ada!", "Hello, Ada!"]));
+}
+
+#[test]
+fn errors_become_failures() {
+    match greet().try_call(&[Value::from("Ada"), Value::Integer(-1)]) {
+        Err(CaatError::RemoteFailure { message, .. }) => assert_eq!(message, "cannot greet -1 times"),
+        other => panic!("expected a remote failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn wrong_arity_is_reported() {
+    match greet().try_call(&[Value::from("Ada")]) {
+        Err(CaatError::RemoteFailure { message, .. }) => assert_eq!(message, "expected 2 arguments, got 1"),
+        other => panic!("expected a remote failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn wrong_types_name_the_parameter() {
+    match greet().try_call(&[Value::from("Ada"), Value::from("two")]) {
+        Err(CaatError::RemoteFailure { message, .. }) => {
+            assert_eq!(message, "argument 2 (`count`) must be i64: Value is not an integer");
+        }
+        other => panic!("expected a remote failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn shell_arguments_are_coerced() {
+    let output = Command::new(common::fixture("greet")).args(["Ada", "1"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), Value::from(vec!["Hello, Ada!"]).to_string());
+
+    let output = Command::new(common::fixture("greet")).args(["Ada", "-3"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "cannot greet -3 times");
+}
+
+#[test]
+fn unreadable_caller_arguments_are_not_mistaken_for_shell_arguments() {
+    for args in ["not json", r#"{"type":"String","value":"Ada"}"#, r#"[{"type":"String"}]"#] {
+        let output = Command::new(common::fixture("greet")).args(["Ada", "1"]).env("CAAT_ARGS", args).output().unwrap();
+        assert_eq!(output.status.code(), Some(1), "{}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("malformed CAAT_ARGS: "), "{}", args);
+    }
+}
+
+#[test]
+fn the_crate_path_can_be_given() {
+    let shout = ForeignFunction::new(common::fixture("shout").to_str().unwrap());
+    assert_eq!(shout.try_call(&[Value::from("hello")]).unwrap(), Value::from("HELLO"));
+}
+
+#[test]
+fn vectors_are_parameters_and_results() {
+    let words = TypedForeignFunction::<(Vec<&str>,), Vec<Vec<String>>>::new(common::fixture("words").to_str().unwrap());
+    let result = words.call((vec!["a b", "", "c"],)).unwrap();
+    assert_eq!(result, vec![vec!["a".to_string(), "b".to_string()], vec![], vec!["c".to_string()]]);
+
+    let words = ForeignFunction::new(common::fixture("words").to_str().unwrap());
+    match words.try_call(&[Value::from(vec![Value::from("a"), Value::Integer(1)])]) {
+        Err(CaatError::RemoteFailure { message, .. }) => assert!(message.contains("`lines`"), "{}", message),
+        other => panic!("expected a remote failure, got {:?}", other),
+    }
+}
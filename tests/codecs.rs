@@ -0,0 +1,82 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use caat_rust::{Caat, CaatError, Codec, ForeignFunction, Value};
+
+fn fixture(name: &str) -> ForeignFunction {
+    ForeignFunction::new(common::fixture(name).to_str().unwrap())
+}
+
+fn nested() -> Value {
+    let mut row = HashMap::new();
+    row.insert("id".to_string(), Value::Integer(-7));
+    row.insert("score".to_string(), Value::Float(0.25));
+    row.insert("tags".to_string(), Value::from(vec!["a", "b"]));
+    row.insert("raw".to_string(), Value::Bytes(vec![0, 255, 10]));
+    Value::from(vec![
+        Value::Map(row, Some("record".to_string())),
+        Value::Map(HashMap::new(), None),
+        Value::Boolean(true),
+        Value::Null,
+        Value::Integer(i64::MAX),
+        Value::Float(f64::INFINITY),
+        Value::from(""),
+        Value::Failure("broken".to_string()),
+        Value::CAATFunction(Arc::new(ForeignFunction::new("wc -l"))),
+    ])
+}
+
+#[test]
+fn every_codec_round_trips_nested_values() {
+    for codec in Codec::AVAILABLE {
+        let decoded = codec.decode(&codec.encode(&nested())).unwrap();
+        assert_eq!(decoded, nested(), "{}", codec);
+    }
+}
+
+#[test]
+fn codecs_are_named() {
+    for codec in Codec::AVAILABLE {
+        assert_eq!(Codec::from_name(codec.name()), Some(*codec));
+    }
+    assert_eq!(Codec::default(), Codec::Json);
+    assert_eq!(Codec::from_name("yaml"), None);
+}
+
+#[test]
+fn garbage_does_not_decode() {
+    for codec in Codec::AVAILABLE {
+        assert!(matches!(codec.decode(&[0xc1, 0xff, 0x00]), Err(CaatError::DecodeError(_))), "{}", codec);
+    }
+}
+
+#[test]
+fn callees_answer_in_the_codec_asked_for() {
+    let args = [nested(), Value::Bytes(vec![1, 2, 3])];
+    for codec in Codec::AVAILABLE {
+        let echo = fixture("echo").with_codec(*codec);
+        assert_eq!(echo.codec(), *codec);
+        assert_eq!(echo.try_call(&args).unwrap(), Value::from(args.to_vec()), "{}", codec);
+    }
+}
+
+#[test]
+fn workers_take_calls_in_their_codec() {
+    for codec in Codec::AVAILABLE {
+        let worker = fixture("counter").with_codec(*codec).spawn_worker().unwrap();
+        let result = worker.try_call(&[Value::Bytes(vec![9])]).unwrap();
+        assert_eq!(result, Value::from(vec![Value::Integer(1), Value::from(vec![Value::Bytes(vec![9])])]), "{}", codec);
+        worker.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn streams_yield_in_the_codec_asked_for() {
+    for codec in Codec::AVAILABLE {
+        let mut stream = fixture("count_to").with_codec(*codec).call_stream(&[Value::Integer(3)]);
+        let values = stream.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)], "{}", codec);
+        assert_eq!(stream.returned(), Some(&Value::from("done")));
+    }
+}
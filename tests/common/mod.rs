@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+
+/// Returns the path of an example program used as a callee.
+///
+/// `cargo test` builds the examples before running the tests, so they sit
+/// next to the test executables in the target directory.
+pub fn fixture(name: &str) -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.push("examples");
+    path.push(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+    assert!(
+        path.exists(),
+        "{} is missing, build the examples first (`cargo build --examples`, with `--release` for benchmarks)",
+        path.display(),
+    );
+    path
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use caat_rust::{Caat, ForeignFunction, Value};
+
+const CALLS: usize = 64;
+const THREADS: usize = 16;
+
+#[test]
+fn concurrent_calls_use_separate_sockets() {
+    let base_dir = std::env::temp_dir().join(format!("caat-test-{}", std::process::id()));
+    caat_rust::socket::set_socket_dir(&base_dir);
+    let socket_dir = caat_rust::socket::socket_dir();
+    let echo = Arc::new(ForeignFunction::new(common::fixture("echo").to_str().unwrap()));
+
+    let (jobs, queue) = mpsc::channel::<usize>();
+    let queue = Arc::new(Mutex::new(queue));
+    let (results, received) = mpsc::channel();
+    let workers = (0..THREADS).map(|_| {
+        let echo = echo.clone();
+        let queue = queue.clone();
+        let results = results.clone();
+        std::thread::spawn(move || loop {
+            let job = match queue.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => break,
+            };
+            let result = echo.try_call(&[Value::Integer(job as i64)]);
+            results.send((job, result)).unwrap();
+        })
+    }).collect::<Vec<_>>();
+
+    for job in 0..CALLS {
+        jobs.send(job).unwrap();
+    }
+    drop(jobs);
+    drop(results);
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let mut seen = received.iter().map(|(job, result)| {
+        let value = result.unwrap_or_else(|e| panic!("call {} failed: {}", job, e));
+        assert_eq!(value, Value::from(vec![Value::Integer(job as i64)]));
+        job
+    }).collect::<Vec<usize>>();
+    seen.sort();
+    assert_eq!(seen, (0..CALLS).collect::<Vec<usize>>());
+
+    let leftover = std::fs::read_dir(&socket_dir).unwrap().count();
+    assert_eq!(leftover, 0, "sockets were left in {}", socket_dir.display());
+    let _ = std::fs::remove_dir(&socket_dir);
+    let _ = std::fs::remove_dir(&base_dir);
+}
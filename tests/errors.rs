@@ -0,0 +1,56 @@
+mod common;
+
+use caat_rust::{Caat, CaatError, ForeignFunction, Value};
+
+fn fail(how: &str, message: &str) -> Result<Value, CaatError> {
+    let fail = ForeignFunction::new(common::fixture("fail").to_str().unwrap());
+    fail.try_call(&[Value::from(how), Value::from(message)])
+}
+
+#[test]
+fn missing_commands_fail_to_spawn() {
+    let missing = ForeignFunction::new("caat-no-such-command");
+    match missing.try_call(&[]) {
+        Err(CaatError::SpawnFailed(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+        other => panic!("expected a spawn failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn non_zero_exits_carry_code_and_stderr() {
+    match fail("exit", "disk on fire") {
+        Err(CaatError::NonZeroExit { code, signal, stderr }) => {
+            assert_eq!(code, Some(3));
+            assert_eq!(signal, None);
+            assert_eq!(stderr.trim(), "disk on fire");
+        }
+        other => panic!("expected a non-zero exit, got {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn killed_callees_report_the_signal() {
+    match fail("abort", "giving up") {
+        Err(CaatError::NonZeroExit { code, signal, stderr }) => {
+            assert_eq!(code, None);
+            assert_eq!(signal, Some(libc::SIGABRT));
+            assert!(stderr.contains("giving up"), "{}", stderr);
+        }
+        other => panic!("expected a non-zero exit, got {:?}", other),
+    }
+}
+
+#[test]
+fn failure_data_reaches_the_caller() {
+    match fail("data", "quota exceeded") {
+        Err(CaatError::RemoteFailure { message, data }) => {
+            assert_eq!(message, "quota exceeded");
+            assert_eq!(data, Some(Value::from(vec![("code".to_string(), Value::Integer(42))])));
+        }
+        other => panic!("expected a remote failure, got {:?}", other),
+    }
+    let fail = ForeignFunction::new(common::fixture("fail").to_str().unwrap());
+    let value = fail.call(&[Value::from("data"), Value::from("quota exceeded")]);
+    assert_eq!(value, Value::Failure("quota exceeded".to_string()));
+}
@@ -0,0 +1,88 @@
+use caat_rust::format::{self, FormatError};
+use caat_rust::Value;
+
+fn map(entries: Vec<(&str, Value)>) -> Value {
+    Value::from(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect::<Vec<_>>())
+}
+
+fn invalid(format: &str, reason: &str) -> Result<(), FormatError> {
+    Err(FormatError::Invalid { format: format.to_string(), reason: reason.to_string() })
+}
+
+#[test]
+fn records_need_named_fields() {
+    let record = map(vec![("name", Value::from("ada")), ("age", Value::Integer(36))]).with_format("record");
+    assert_eq!(record.validate_format(), Ok(()));
+    let unnamed = map(vec![("", Value::Null)]).with_format("record");
+    assert_eq!(unnamed.validate_format(), invalid("record", "field names must not be empty"));
+}
+
+#[test]
+fn tables_need_columns_of_equal_length() {
+    let table = map(vec![
+        ("id", Value::from(vec![1i64, 2])),
+        ("name", Value::from(vec!["a", "b"])),
+    ]).with_format("table");
+    assert_eq!(table.validate_format(), Ok(()));
+
+    let scalar = map(vec![("id", Value::Integer(1))]).with_format("table");
+    assert_eq!(scalar.validate_format(), invalid("table", "column \"id\" is not a list"));
+
+    let ragged = map(vec![("id", Value::from(vec![1i64, 2])), ("name", Value::from(vec!["a"]))]).with_format("table");
+    match ragged.validate_format() {
+        Err(FormatError::Invalid { format, reason }) => {
+            assert_eq!(format, "table");
+            assert!(reason.contains("rows, expected"), "{}", reason);
+        }
+        other => panic!("expected a ragged table to be invalid, got {:?}", other),
+    }
+}
+
+#[test]
+fn csv_rows_hold_only_scalars() {
+    let row = map(vec![
+        ("a", Value::from("x")),
+        ("b", Value::Integer(1)),
+        ("c", Value::Float(0.5)),
+        ("d", Value::Boolean(true)),
+        ("e", Value::Null),
+    ]).with_format("csv-row");
+    assert_eq!(row.validate_format(), Ok(()));
+    let nested = map(vec![("a", Value::from(vec![1i64]))]).with_format("csv-row");
+    assert_eq!(nested.validate_format(), invalid("csv-row", "cell \"a\" is not a scalar"));
+}
+
+#[test]
+fn unknown_formats_are_errors() {
+    let value = map(vec![]).with_format("no-such-format");
+    assert_eq!(value.validate_format(), Err(FormatError::Unknown("no-such-format".to_string())));
+    assert!(!format::is_known_format("no-such-format"));
+}
+
+#[test]
+fn untagged_maps_and_other_values_are_valid() {
+    assert_eq!(map(vec![("", Value::Null)]).validate_format(), Ok(()));
+    assert_eq!(Value::Integer(1).validate_format(), Ok(()));
+    assert_eq!(Value::Integer(1).with_format("record"), Value::Integer(1));
+    assert_eq!(Value::Integer(1).map_format(), None);
+    assert_eq!(map(vec![]).map_format(), None);
+    assert_eq!(map(vec![]).with_format("record").map_format(), Some("record"));
+}
+
+#[test]
+fn custom_formats_can_be_registered() {
+    format::register_format("point", |entries| {
+        match (entries.get("x"), entries.get("y"), entries.len()) {
+            (Some(Value::Float(_)), Some(Value::Float(_)), 2) => Ok(()),
+            _ => Err("a point has a float x and y".to_string()),
+        }
+    });
+    assert!(format::is_known_format("point"));
+    assert!(format::known_formats().contains(&"point".to_string()));
+
+    let point = map(vec![("x", Value::Float(1.0)), ("y", Value::Float(2.0))]);
+    assert_eq!(format::validate("point", point.as_map().unwrap()), Ok(()));
+    assert_eq!(point.with_format("point").validate_format(), Ok(()));
+    let line = map(vec![("x", Value::Float(1.0))]).with_format("point");
+    assert_eq!(line.validate_format(), invalid("point", "a point has a float x and y"));
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::io::Read;
+use std::process::Command;
+use caat_rust::frame::{MAGIC, PROTOCOL_VERSION};
+use caat_rust::{Caat, CaatError, ForeignFunction, Value};
+use interprocess::local_socket::LocalSocketListener;
+
+const SEVEN: &str = r#"{"type":"Integer","value":7}"#;
+
+/// A frame header for a JSON `Return` of `length` bytes.
+fn header(magic: [u8; 4], version: u8, length: u32) -> Vec<u8> {
+    let mut header = magic.to_vec();
+    header.extend_from_slice(&[version, 1, 1, 0]);
+    header.extend_from_slice(&length.to_be_bytes());
+    header
+}
+
+fn frame(version: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = header(MAGIC, version, payload.len() as u32);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Calls the raw fixture, which writes `bytes` to the socket as its answer.
+fn answer(bytes: &[u8]) -> Result<Value, CaatError> {
+    let raw = ForeignFunction::new(common::fixture("raw").to_str().unwrap());
+    let hex = bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    raw.try_call(&[Value::from(hex)])
+}
+
+#[test]
+fn framed_answers_are_read() {
+    assert_eq!(answer(&frame(PROTOCOL_VERSION, SEVEN.as_bytes())).unwrap(), Value::Integer(7));
+    assert_eq!(answer(&frame(1, SEVEN.as_bytes())).unwrap(), Value::Integer(7));
+}
+
+#[test]
+fn bare_json_is_still_accepted() {
+    assert_eq!(answer(SEVEN.as_bytes()).unwrap(), Value::Integer(7));
+    // Shorter than the magic bytes, so it cannot be a frame either.
+    assert!(matches!(answer(b"7"), Err(CaatError::DecodeError(_))));
+}
+
+#[test]
+fn newer_versions_are_incompatible() {
+    match answer(&frame(PROTOCOL_VERSION + 1, SEVEN.as_bytes())) {
+        Err(CaatError::IncompatibleVersion { ours, theirs }) => {
+            assert_eq!(ours, PROTOCOL_VERSION);
+            assert_eq!(theirs, PROTOCOL_VERSION + 1);
+        }
+        other => panic!("expected an incompatible version, got {:?}", other),
+    }
+    assert!(matches!(answer(&frame(0, SEVEN.as_bytes())), Err(CaatError::IncompatibleVersion { theirs: 0, .. })));
+}
+
+#[test]
+fn truncated_frames_are_protocol_errors() {
+    let short_header = header(MAGIC, PROTOCOL_VERSION, 0)[..6].to_vec();
+    assert!(matches!(answer(&short_header), Err(CaatError::ProtocolError(_))));
+
+    let mut short_payload = header(MAGIC, PROTOCOL_VERSION, 100);
+    short_payload.extend_from_slice(&[b' '; 10]);
+    match answer(&short_payload) {
+        Err(CaatError::ProtocolError(message)) => assert!(message.contains("10 of 100"), "{}", message),
+        other => panic!("expected a protocol error, got {:?}", other),
+    }
+}
+
+#[test]
+fn bad_magic_is_read_as_bare_json() {
+    let mut bytes = header(*b"CAAX", PROTOCOL_VERSION, SEVEN.len() as u32);
+    bytes.extend_from_slice(SEVEN.as_bytes());
+    assert!(matches!(answer(&bytes), Err(CaatError::DecodeError(_))));
+}
+
+/// Runs the echo fixture against a socket of our own and returns the raw
+/// bytes of its answer.
+fn raw_answer(protocol: Option<&str>) -> Vec<u8> {
+    let socket = std::env::temp_dir().join(format!("caat-frames-{}-{}.sock", std::process::id(), protocol.unwrap_or("none")));
+    let _ = std::fs::remove_file(&socket);
+    let listener = LocalSocketListener::bind(socket.to_str().unwrap()).unwrap();
+    let mut command = Command::new(common::fixture("echo"));
+    command.env("CAAT_SOCKET", &socket).env_remove("CAAT_PROTOCOL").env_remove("CAAT_ARGS");
+    if let Some(protocol) = protocol {
+        command.env("CAAT_PROTOCOL", protocol);
+    }
+    let mut child = command.spawn().unwrap();
+    let mut bytes = Vec::new();
+    listener.accept().unwrap().read_to_end(&mut bytes).unwrap();
+    assert!(child.wait().unwrap().success());
+    let _ = std::fs::remove_file(&socket);
+    bytes
+}
+
+#[test]
+fn callees_answer_in_the_advertised_version() {
+    for (advertised, version) in [("1", 1), ("3", 3), (" 5 ", 5), ("200", PROTOCOL_VERSION)] {
+        let bytes = raw_answer(Some(advertised));
+        assert_eq!(bytes[..4], MAGIC, "{}", advertised);
+        assert_eq!(bytes[4], version, "{}", advertised);
+    }
+}
+
+#[test]
+fn callees_answer_bare_json_without_a_usable_version() {
+    for advertised in [None, Some("0"), Some("five")] {
+        let bytes = raw_answer(advertised);
+        assert_ne!(bytes[..4], MAGIC, "{:?}", advertised);
+        assert!(json::parse(std::str::from_utf8(&bytes).unwrap()).is_ok(), "{:?}", advertised);
+    }
+}
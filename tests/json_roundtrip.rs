@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use caat_rust::{ForeignFunction, Value};
+use proptest::prelude::*;
+
+fn leaf() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        any::<i64>().prop_map(Value::Integer),
+        any::<String>().prop_map(Value::String),
+        any::<Vec<u8>>().prop_map(Value::Bytes),
+        any::<i128>().prop_map(Value::from),
+        (any::<i64>(), 0u32..=28).prop_map(|(n, scale)| Value::Decimal(caat_rust::Decimal::new(n, scale))),
+        (-62_167_219_200i64..=253_402_300_799, 0u32..1_000_000_000)
+            .prop_map(|(secs, nanos)| Value::Timestamp(caat_rust::Timestamp::from_unix(secs, nanos).unwrap())),
+        (any::<u64>(), 0u32..1_000_000_000).prop_map(|(secs, nanos)| Value::Duration(std::time::Duration::new(secs, nanos))),
+        any::<f64>().prop_map(Value::Float),
+        prop_oneof![Just(f64::NAN), Just(f64::INFINITY), Just(f64::NEG_INFINITY), Just(-0.0)]
+            .prop_map(Value::Float),
+        any::<bool>().prop_map(Value::Boolean),
+        Just(Value::Null),
+        any::<String>().prop_map(Value::Failure),
+        "[a-z]{1,8}( [a-z0-9\"\\\\]{1,4}){0,2}"
+            .prop_map(|command| Value::CAATFunction(Arc::new(ForeignFunction::new(&command)))),
+    ]
+}
+
+fn value() -> impl Strategy<Value = Value> {
+    leaf().prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+            (prop::collection::hash_map(any::<String>(), inner, 0..8), prop::option::of(any::<String>()))
+                .prop_map(|(map, format)| Value::Map(map, format)),
+        ]
+    })
+}
+
+/// Structural equality that also treats NaN as equal to itself and tells
+/// `0.0` and `-0.0` apart.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(f), Value::Float(g)) => {
+            (f.is_nan() && g.is_nan()) || f.to_bits() == g.to_bits()
+        }
+        (Value::Map(d, f), Value::Map(e, g)) => {
+            f == g && d.len() == e.len()
+                && d.iter().all(|(key, value)| e.get(key).is_some_and(|other| same(value, other)))
+        }
+        (Value::List(l), Value::List(m)) => {
+            l.len() == m.len() && l.iter().zip(m.iter()).all(|(x, y)| same(x, y))
+        }
+        _ => a == b,
+    }
+}
+
+fn round_trip(value: &Value) -> Value {
+    let json = value.to_json();
+    assert!(json::parse(&json).is_ok(), "invalid JSON: {}", json);
+    Value::from_json(&json).expect("failed to decode")
+}
+
+proptest! {
+    #[test]
+    fn every_value_round_trips(v in value()) {
+        let decoded = round_trip(&v);
+        prop_assert!(same(&v, &decoded), "{:?} != {:?}", v, decoded);
+        prop_assert_eq!(v.to_json(), decoded.to_json());
+    }
+
+    #[test]
+    fn argument_lists_round_trip(args in prop::collection::vec(value(), 0..6)) {
+        let json = json::parse(&Value::as_json(&args)).unwrap();
+        let decoded = json.members()
+            .map(|v| Value::from_json_value(v).unwrap())
+            .collect::<Vec<Value>>();
+        prop_assert_eq!(args.len(), decoded.len());
+        for (a, b) in args.iter().zip(decoded.iter()) {
+            prop_assert!(same(a, b), "{:?} != {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn strings_are_escaped() {
+    for s in ["\"quoted\"", "back\\slash", "new\nline", "tab\t", "\u{0}\u{1f}", "emoji 🦀"] {
+        let value = Value::String(s.to_string());
+        assert_eq!(round_trip(&value), value);
+        let failure = Value::Failure(s.to_string());
+        assert_eq!(round_trip(&failure), failure);
+    }
+}
+
+#[test]
+fn map_keys_are_escaped() {
+    let mut map = HashMap::new();
+    map.insert("\"key\"".to_string(), Value::Integer(1));
+    map.insert("a\\b".to_string(), Value::Null);
+    let value = Value::Map(map, None);
+    assert_eq!(round_trip(&value), value);
+}
+
+#[test]
+fn empty_collections() {
+    let empty_map = Value::Map(HashMap::new(), None);
+    let empty_list = Value::List(Box::new([]));
+    assert_eq!(round_trip(&empty_map), empty_map);
+    assert_eq!(round_trip(&empty_list), empty_list);
+    assert_eq!(Value::as_json(&[]), "[]");
+}
+
+#[test]
+fn non_finite_floats() {
+    for f in [f64::INFINITY, f64::NEG_INFINITY] {
+        assert_eq!(round_trip(&Value::Float(f)), Value::Float(f));
+    }
+    match round_trip(&Value::Float(f64::NAN)) {
+        Value::Float(f) => assert!(f.is_nan()),
+        other => panic!("expected a float, got {:?}", other),
+    }
+}
+
+#[test]
+fn floats_keep_full_precision() {
+    for f in [0.1, 1.0 / 3.0, f64::MAX, f64::MIN_POSITIVE, 5e-324, 123_456_789.123_456_79] {
+        assert_eq!(round_trip(&Value::Float(f)), Value::Float(f));
+    }
+}
+
+#[test]
+fn map_format_round_trips() {
+    let mut map = HashMap::new();
+    map.insert("format".to_string(), Value::String("an entry".to_string()));
+    let value = Value::Map(map, Some("record".to_string()));
+    let decoded = round_trip(&value);
+    assert_eq!(decoded.map_format(), Some("record"));
+    assert_eq!(decoded, value);
+}
+
+#[test]
+fn legacy_inner_format_is_a_tag() {
+    let json = r#"{"type": "Map", "value": {"a": {"type": "Integer", "value": 1}, "format": "table"}}"#;
+    let decoded = Value::from_json(json).unwrap();
+    assert_eq!(decoded.map_format(), Some("table"));
+    assert_eq!(decoded, Value::from(vec![("a".to_string(), Value::Integer(1))]).with_format("table"));
+}
+
+#[test]
+fn malformed_payloads_are_rejected() {
+    for json in [
+        r#"{"type": "Integer", "value": "1"}"#,
+        r#"{"type": "Integer", "value": 1.5}"#,
+        r#"{"type": "Float", "value": "one"}"#,
+        r#"{"type": "Float", "value": null}"#,
+        r#"{"type": "String", "value": 1}"#,
+        r#"{"type": "Failure", "value": null}"#,
+        r#"{"type": "Boolean", "value": "true"}"#,
+        r#"{"type": "List", "value": [{"type": "Integer", "value": "1"}]}"#,
+    ] {
+        assert_eq!(Value::from_json(json), None, "{}", json);
+    }
+}
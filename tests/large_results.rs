@@ -0,0 +1,31 @@
+mod common;
+
+use caat_rust::{Caat, ForeignFunction, Value};
+
+const PATTERN: &str = "The \"quick\" brown fox\\jumps\nover the lazy dog 🦀 ";
+
+fn expected(length: usize) -> Value {
+    Value::from(PATTERN.chars().cycle().take(length).collect::<String>())
+}
+
+fn large(length: usize) -> Value {
+    let large = ForeignFunction::new(common::fixture("large").to_str().unwrap());
+    large.try_call(&[Value::from(length.to_string())]).unwrap()
+}
+
+#[test]
+fn results_over_a_kilobyte_are_not_truncated() {
+    for length in [1000, 1024, 1025, 1100, 4097] {
+        let result = large(length);
+        assert_eq!(result.to_json(), expected(length).to_json(), "length {}", length);
+    }
+}
+
+#[test]
+fn results_of_hundreds_of_kilobytes_arrive_whole() {
+    let result = large(600_000);
+    let expected = expected(600_000);
+    assert!(expected.to_json().len() > 600_000);
+    assert_eq!(result.to_json(), expected.to_json());
+    assert_eq!(result, expected);
+}
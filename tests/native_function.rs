@@ -0,0 +1,43 @@
+mod common;
+
+use caat_rust::{Caat, CaatExt, ForeignFunction, NativeFunction, Value};
+
+fn sum() -> NativeFunction {
+    NativeFunction::new("sum", |args| {
+        let mut total = 0;
+        for arg in args {
+            match arg {
+                Value::Integer(i) => total += i,
+                Value::List(l) => total += l.iter().filter_map(|v| i64::try_from(v.clone()).ok()).sum::<i64>(),
+                other => return Value::Failure(format!("cannot add {}", other)),
+            }
+        }
+        Value::Integer(total)
+    })
+}
+
+#[test]
+fn closures_are_called_in_process() {
+    assert_eq!(sum().call(&[Value::Integer(2), Value::Integer(3)]), Value::Integer(5));
+    assert_eq!(sum().to_string(), "sum");
+    assert!(sum().try_call(&[Value::Null]).is_err());
+}
+
+#[test]
+fn native_and_foreign_functions_mix() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    let pipeline = echo.then(sum());
+    assert_eq!(pipeline.call(&[Value::Integer(4), Value::Integer(5)]), Value::Integer(9));
+}
+
+#[test]
+fn native_functions_are_values() {
+    let value = sum().into_value();
+    assert_eq!(value, sum().into_value());
+    match value {
+        Value::CAATFunction(function) => {
+            assert_eq!(function.call(&[Value::Integer(1), Value::Integer(1)]), Value::Integer(2));
+        }
+        other => panic!("expected a function, got {:?}", other),
+    }
+}
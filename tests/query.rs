@@ -0,0 +1,87 @@
+use caat_rust::query::QueryError;
+use caat_rust::Value;
+
+/// `{"users": [{"name": "ada", "age": 36}, {"name": "alan", "age": "41"}],
+/// "meta": {"content-type": "users", "count": 2}}`
+fn users() -> Value {
+    let user = |name: &str, age: Value| Value::from(vec![
+        ("name".to_string(), Value::from(name)),
+        ("age".to_string(), age),
+    ]);
+    Value::from(vec![
+        ("users".to_string(), Value::from(vec![user("ada", Value::Integer(36)), user("alan", Value::from("41"))])),
+        ("meta".to_string(), Value::from(vec![
+            ("content-type".to_string(), Value::from("users")),
+            ("count".to_string(), Value::Integer(2)),
+        ])),
+    ])
+}
+
+#[test]
+fn indexing_gives_null_when_missing() {
+    let value = users();
+    assert_eq!(value["users"][0]["name"].as_str(), Some("ada"));
+    assert_eq!(value["meta"]["count"].as_i64(), Some(2));
+    assert!(value["users"][5]["name"].is_null());
+    assert!(value["meta"][0].is_null());
+    assert_eq!(value.get("users").and_then(|u| u.as_list()).map(|u| u.len()), Some(2));
+    assert!(value.get("nobody").is_none());
+}
+
+#[test]
+fn get_mut_edits_in_place() {
+    let mut value = users();
+    *value.get_mut("users").unwrap().get_mut(1).unwrap().get_mut("age").unwrap() = Value::Integer(41);
+    assert_eq!(value.query_as::<u8>("users[1].age"), Ok(41));
+    *value.query_mut("meta.count").unwrap() = Value::Integer(3);
+    assert_eq!(value["meta"]["count"], Value::Integer(3));
+    value.as_map_mut().unwrap().remove("meta");
+    assert_eq!(value.query_mut("meta.count").unwrap_err(), QueryError::Missing { path: "meta".to_string() });
+}
+
+#[test]
+fn paths_follow_keys_and_indexes() {
+    let value = users();
+    assert_eq!(value.query("users[0].name").unwrap().as_str(), Some("ada"));
+    assert_eq!(value.query("meta[\"content-type\"]").unwrap().as_str(), Some("users"));
+    assert_eq!(value.query("").unwrap(), &value);
+    assert_eq!(value.query("users[2].name").unwrap_err(), QueryError::Missing { path: "users[2]".to_string() });
+    assert_eq!(
+        value.query("users[0].name.first").unwrap_err(),
+        QueryError::NotIndexable { path: "users[0].name".to_string(), found: "a string" },
+    );
+    assert!(matches!(value.query("users[*].name"), Err(QueryError::Ambiguous { .. })));
+}
+
+#[test]
+fn wildcards_match_every_element() {
+    let value = users();
+    let names = value.query_all_as::<String>("users[*].name").unwrap();
+    assert_eq!(names, ["ada", "alan"]);
+    let meta = value.query_all("meta.*").unwrap();
+    assert_eq!(meta, [&Value::from("users"), &Value::Integer(2)]);
+    assert_eq!(value.query_all("users[*].age").unwrap().len(), 2);
+}
+
+#[test]
+fn conversion_errors_name_the_path() {
+    let value = users();
+    let error = value.query_all_as::<i64>("users[*].age").unwrap_err();
+    assert_eq!(error, QueryError::Conversion { path: "users[1].age".to_string(), reason: "Value is not an integer" });
+    assert_eq!(error.to_string(), "users[1].age: Value is not an integer");
+    assert_eq!(
+        value.query_as::<bool>("meta.count").unwrap_err().to_string(),
+        "meta.count: Value is not a boolean",
+    );
+}
+
+#[test]
+fn malformed_paths_are_rejected() {
+    let value = users();
+    for (path, position) in [("users[", 6), ("users[0", 7), ("users.", 6), ("users[0]name", 8), ("meta[\"count]", 5)] {
+        match value.query(path) {
+            Err(QueryError::Syntax { position: p, .. }) => assert_eq!(p, position, "{}", path),
+            other => panic!("{}: {:?}", path, other),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::process::Command;
+use caat_rust::{Caat, CaatError, ForeignFunction, Value};
+
+#[test]
+fn values_are_printed_without_a_caller() {
+    let echo = common::fixture("echo");
+    let output = Command::new(&echo).args(["a", "b"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let expected = Value::from(vec![echo.to_str().unwrap(), "a", "b"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), expected.to_string());
+}
+
+#[test]
+fn failures_are_printed_to_stderr_without_a_caller() {
+    let output = Command::new(common::fixture("fail")).args(["failure", "out of cheese"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "out of cheese");
+}
+
+#[test]
+fn unreadable_caller_arguments_are_reported() {
+    let output = Command::new(common::fixture("echo")).args(["a", "b"]).env("CAAT_ARGS", "[1, 2").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("malformed CAAT_ARGS: "));
+}
+
+#[test]
+fn return_caat_converts_its_argument() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    assert_eq!(echo.try_call(&[Value::Integer(1), Value::from("two")]).unwrap(), Value::from(vec![Value::Integer(1), Value::from("two")]));
+}
+
+#[test]
+fn return_caat_delivers_failures() {
+    let fail = ForeignFunction::new(common::fixture("fail").to_str().unwrap());
+    match fail.try_call(&[Value::from("failure"), Value::from("out of cheese")]) {
+        Err(CaatError::RemoteFailure { message, data: None }) => assert_eq!(message, "out of cheese"),
+        other => panic!("expected a remote failure, got {:?}", other),
+    }
+}
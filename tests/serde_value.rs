@@ -0,0 +1,95 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use std::collections::HashMap;
+use caat_rust::{from_value, to_value, Caat, ForeignFunction, Value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Point {
+    x: i32,
+    y: f64,
+    label: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Shape {
+    Empty,
+    Circle(Point, f64),
+    Polygon { points: Vec<Point> },
+}
+
+fn map(entries: Vec<(&str, Value)>) -> Value {
+    let map = entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect::<HashMap<_, _>>();
+    Value::Map(map, None)
+}
+
+#[test]
+fn structs_become_maps() {
+    let point = Point { x: 1, y: 2.5, label: None };
+    let value = to_value(&point).unwrap();
+    assert_eq!(value, map(vec![("x", Value::Integer(1)), ("y", Value::Float(2.5)), ("label", Value::Null)]));
+    assert_eq!(from_value::<Point>(value).unwrap(), point);
+}
+
+#[test]
+fn enums_are_externally_tagged() {
+    assert_eq!(to_value(&Shape::Empty).unwrap(), Value::from("Empty"));
+
+    let shapes = vec![
+        Shape::Empty,
+        Shape::Circle(Point { x: 0, y: 0.0, label: Some("origin".to_string()) }, 1.0),
+        Shape::Polygon { points: vec![Point { x: 1, y: 1.0, label: None }] },
+    ];
+    let value = to_value(&shapes).unwrap();
+    assert_eq!(from_value::<Vec<Shape>>(value).unwrap(), shapes);
+}
+
+#[test]
+fn values_round_trip() {
+    let value = Value::from(vec![
+        Value::Integer(-3),
+        Value::from("text"),
+        Value::Failure("boom".to_string()),
+        map(vec![("nested", Value::from(vec![Value::Boolean(true), Value::Null]))]),
+    ]);
+    assert_eq!(to_value(&value).unwrap(), value);
+    assert_eq!(from_value::<Value>(value.clone()).unwrap(), value);
+}
+
+#[test]
+fn failures_do_not_decode_into_other_types() {
+    let error = from_value::<Point>(Value::Failure("no such point".to_string())).unwrap_err();
+    assert!(error.to_string().contains("no such point"), "{}", error);
+}
+
+#[test]
+fn out_of_range_integers_are_errors() {
+    assert!(from_value::<u8>(Value::Integer(256)).is_err());
+    assert!(from_value::<i64>(to_value(&u64::MAX).unwrap()).is_err());
+}
+
+#[test]
+fn wide_integers_round_trip_as_big_integers() {
+    let value = to_value(&u64::MAX).unwrap();
+    assert_eq!(value, Value::BigInt(u64::MAX.into()));
+    assert_eq!(from_value::<u64>(value.clone()).unwrap(), u64::MAX);
+    assert_eq!(from_value::<Value>(value.clone()).unwrap(), value);
+    assert_eq!(from_value::<i128>(to_value(&i128::MIN).unwrap()).unwrap(), i128::MIN);
+    let huge = Value::BigInt("123456789012345678901234567890123456789012".parse().unwrap());
+    assert_eq!(from_value::<Value>(huge.clone()).unwrap(), huge);
+    assert_eq!(to_value(&huge).unwrap(), huge);
+    let small = Value::BigInt(7.into());
+    assert_eq!(to_value(&small).unwrap(), Value::Integer(7));
+    assert_eq!(from_value::<Value>(small).unwrap(), Value::Integer(7));
+}
+
+#[test]
+fn serde_types_pass_through_a_call() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    let point = Point { x: 4, y: -1.5, label: Some("p".to_string()) };
+    let result = echo.try_call(&[to_value(&point).unwrap()]).unwrap();
+    let (echoed,): (Point,) = from_value(result).unwrap();
+    assert_eq!(echoed, point);
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "macros")]
+
+mod common;
+
+use caat_rust::callee::ArgumentError;
+use caat_rust::signature::{Param, Signature, ValueType};
+use caat_rust::{CaatError, Caat, ForeignFunction, Value};
+
+fn greet() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("greet").to_str().unwrap())
+}
+
+#[test]
+fn callees_describe_themselves() {
+    let signature = greet().signature().unwrap();
+    assert_eq!(signature.params, vec![
+        Param { name: "name".to_string(), value_type: ValueType::String },
+        Param { name: "count".to_string(), value_type: ValueType::Integer },
+    ]);
+    assert_eq!(signature.returns, ValueType::List);
+    assert_eq!(signature.description.as_deref(), Some("Greets someone a number of times."));
+    assert_eq!(signature.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn known_signatures_check_arguments() {
+    let greet = greet();
+    greet.signature().unwrap();
+    match greet.try_call(&[Value::from("Ada"), Value::Float(2.0)]) {
+        Err(CaatError::ArgumentMismatch(ArgumentError::Type { position, name, .. })) => {
+            assert_eq!((position, name.as_str()), (2, "count"));
+        }
+        other => panic!("expected an argument mismatch, got {:?}", other),
+    }
+    match greet.try_call(&[]) {
+        Err(CaatError::ArgumentMismatch(ArgumentError::Arity { expected: 2, found: 0 })) => (),
+        other => panic!("expected an argument mismatch, got {:?}", other),
+    }
+    assert!(greet.try_call(&[Value::from("Ada"), Value::Integer(1)]).is_ok());
+}
+
+#[test]
+fn signatures_round_trip() {
+    let signature = Signature {
+        params: vec![Param { name: "x".to_string(), value_type: ValueType::Any }],
+        returns: ValueType::Null,
+        description: None,
+        version: Some("1.0.0".to_string()),
+    };
+    let value = Value::from_json(&signature.to_value().to_json()).unwrap();
+    assert_eq!(Signature::from_value(&value), Some(signature));
+}
+
+#[test]
+fn other_callees_have_no_signature() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    assert!(matches!(echo.signature(), Err(CaatError::ProtocolError(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn other_commands_see_an_unknown_option_rather_than_a_call() {
+    let script = std::env::temp_dir().join(format!("caat-introspect-{}.sh", std::process::id()));
+    let report = script.with_extension("sh.args");
+    std::fs::write(&script, "printf '%s\\n' \"${CAAT_ARGS-unset}\" \"$@\" > \"$0.args\"\n").unwrap();
+    let command = ForeignFunction::new(&format!("sh {} first", script.display()));
+    assert!(command.signature().is_err());
+    let seen = std::fs::read_to_string(&report).unwrap();
+    let _ = std::fs::remove_file(&script);
+    let _ = std::fs::remove_file(&report);
+    assert_eq!(seen, "unset\nfirst\n--caat-signature\n");
+}
+
+#[test]
+fn the_handshake_needs_the_variable_too() {
+    let output = std::process::Command::new(common::fixture("greet")).arg("--caat-signature").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "expected 2 arguments, got 1");
+}
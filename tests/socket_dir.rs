@@ -0,0 +1,43 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::Path;
+use caat_rust::{Caat, CaatError, ForeignFunction, Value};
+
+fn mode(path: &Path) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+// The socket directory is global to the process, so everything that changes
+// it runs in this one test.
+#[test]
+fn given_directories_are_left_alone() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    let uid = unsafe { libc::getuid() };
+
+    let shared = std::env::temp_dir().join(format!("caat-shared-test-{}", std::process::id()));
+    fs::DirBuilder::new().mode(0o755).create(&shared).unwrap();
+    fs::set_permissions(&shared, fs::Permissions::from_mode(0o755)).unwrap();
+    caat_rust::socket::set_socket_dir(&shared);
+    assert_eq!(caat_rust::socket::socket_dir(), shared.join(format!("caat-{}", uid)));
+    assert_eq!(echo.try_call(&[Value::Integer(1)]).unwrap(), Value::from(vec![1i64]));
+    assert_eq!(mode(&shared), 0o755);
+    assert_eq!(mode(&caat_rust::socket::socket_dir()), 0o700);
+
+    let private = caat_rust::socket::socket_dir();
+    fs::set_permissions(&private, fs::Permissions::from_mode(0o755)).unwrap();
+    match echo.try_call(&[]) {
+        Err(CaatError::SocketBind(e)) => assert_eq!(e.kind(), std::io::ErrorKind::PermissionDenied),
+        other => panic!("expected an open socket directory to be refused, got {:?}", other),
+    }
+    assert_eq!(mode(&private), 0o755);
+
+    fs::remove_dir(&private).unwrap();
+    std::os::unix::fs::symlink(std::env::temp_dir(), &private).unwrap();
+    assert!(matches!(echo.try_call(&[]), Err(CaatError::SocketBind(_))));
+    fs::remove_file(&private).unwrap();
+    fs::remove_dir(&shared).unwrap();
+}
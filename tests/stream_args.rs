@@ -0,0 +1,60 @@
+mod common;
+
+use caat_rust::stream::Stream;
+use caat_rust::{Caat, ForeignFunction, Value};
+
+fn evens() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("evens").to_str().unwrap())
+}
+
+#[test]
+fn streamed_arguments_reach_the_callee() {
+    let result = evens().try_call(&[Stream::new(1..=10i64).into()]);
+    assert_eq!(result.unwrap(), Value::from(vec![2, 4, 6, 8, 10]));
+}
+
+#[test]
+fn callees_read_streams_as_they_go() {
+    // The input never ends, so this only returns if it is not read up front.
+    let result = evens().try_call(&[Stream::new(0i64..).into(), Value::Integer(3)]);
+    assert_eq!(result.unwrap(), Value::from(vec![0, 2, 4]));
+}
+
+#[test]
+fn lists_stand_in_for_streams() {
+    let result = evens().try_call(&[Value::from(vec![1, 2, 3, 4])]);
+    assert_eq!(result.unwrap(), Value::from(vec![2, 4]));
+}
+
+#[test]
+fn streams_are_written_out_as_lists_without_a_callee() {
+    let stream = Value::from(Stream::new(vec!["a", "b"]));
+    assert_eq!(stream.to_json(), Value::from(vec!["a", "b"]).to_json());
+}
+
+#[test]
+fn encoding_a_stream_uses_it_up() {
+    let stream = Stream::new(1..=3i64);
+    let value = Value::from(vec![Value::Stream(stream.clone())]);
+    assert_eq!(Value::from_json(&value.to_json()), Some(Value::from(vec![Value::from(vec![1, 2, 3])])));
+    assert_eq!(stream.count(), 0);
+}
+
+#[test]
+fn functions_and_streams_inside_streamed_values_stay_the_callers() {
+    use caat_rust::{CaatExt, NativeFunction};
+
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    let double = NativeFunction::new("double", |args| match args {
+        [Value::Integer(i)] => Value::Integer(i * 2),
+        _ => Value::Null,
+    });
+    let inner = Value::from(Stream::new(1..=2i64));
+    let result = echo.try_call(&[Stream::new(vec![double.into_value(), inner]).into()]).unwrap();
+    let values = &result[0];
+    match &values[0] {
+        Value::CAATFunction(function) => assert_eq!(function.call(&[Value::Integer(4)]), Value::Integer(8)),
+        other => panic!("expected a function, got {:?}", other),
+    }
+    assert_eq!(values[1], Value::from(vec![1, 2]));
+}
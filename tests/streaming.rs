@@ -0,0 +1,122 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use caat_rust::{Caat, CaatError, ForeignFunction, Value};
+
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_to() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("count_to").to_str().unwrap())
+}
+
+fn report_path() -> PathBuf {
+    let n = REPORTS.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("caat-count-to-{}-{}", std::process::id(), n))
+}
+
+/// The pid and the last number count_to wrote to `report`, once it has
+/// written them.
+fn read_report(report: &Path) -> (u32, i64) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let contents = std::fs::read_to_string(report).unwrap_or_default();
+        if let Some((pid, last)) = contents.split_once(' ') {
+            if let (Ok(pid), Ok(last)) = (pid.parse(), last.parse()) {
+                return (pid, last);
+            }
+        }
+        assert!(Instant::now() < deadline, "count_to did not report: {:?}", contents);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Whether `pid` is gone, reaped rather than a zombie.
+#[cfg(unix)]
+fn gone(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == -1 }
+}
+
+#[test]
+fn streams_yield_every_value_then_end() {
+    let mut stream = count_to().call_stream(&[Value::Integer(5)]);
+    let values = stream.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(values, (1..=5).map(Value::Integer).collect::<Vec<_>>());
+    assert_eq!(stream.returned(), Some(&Value::from("done")));
+}
+
+#[test]
+fn streams_without_values_only_return() {
+    let mut stream = count_to().call_stream(&[Value::Integer(0)]);
+    assert!(stream.next().is_none());
+    assert_eq!(stream.returned(), Some(&Value::from("done")));
+}
+
+#[test]
+fn failed_streams_end_with_the_error() {
+    let items = count_to().call_stream(&[Value::Integer(2), Value::Boolean(true)]).collect::<Vec<_>>();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].as_ref().unwrap(), &Value::Integer(1));
+    assert_eq!(items[1].as_ref().unwrap(), &Value::Integer(2));
+    match &items[2] {
+        Err(CaatError::NonZeroExit { code: Some(3), .. }) => (),
+        other => panic!("unexpected item: {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn dropping_a_stream_stops_the_callee() {
+    let report = report_path();
+    let mut stream = count_to().call_stream(&[Value::Integer(i64::MAX), Value::from(report.to_str().unwrap())]);
+    let first = stream.by_ref().take(3).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(first, vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]);
+    let (pid, _) = read_report(&report);
+    let started = Instant::now();
+    drop(stream);
+    // Dropping waits for the callee, so it is gone by now.
+    assert!(gone(pid), "process {} is still around", pid);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let _ = std::fs::remove_file(&report);
+}
+
+#[cfg(unix)]
+#[test]
+fn callees_wait_for_slow_readers() {
+    let report = report_path();
+    let mut stream = count_to().call_stream(&[Value::Integer(i64::MAX), Value::from(report.to_str().unwrap())]);
+    assert_eq!(stream.next().unwrap().unwrap(), Value::Integer(1));
+
+    // Without a reader the callee fills the socket buffer and then blocks.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut last = read_report(&report).1;
+    loop {
+        std::thread::sleep(Duration::from_millis(50));
+        let now = read_report(&report).1;
+        if now == last {
+            break;
+        }
+        assert!(Instant::now() < deadline, "the callee kept going, at {}", now);
+        last = now;
+    }
+    assert!(last < 100_000, "the callee got {} values ahead", last);
+
+    let more = stream.by_ref().take(last as usize + 10).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(more.last(), Some(&Value::Integer(last + 11)));
+    assert!(read_report(&report).1 > last);
+    let (pid, _) = read_report(&report);
+    drop(stream);
+    assert!(gone(pid), "process {} is still around", pid);
+    let _ = std::fs::remove_file(&report);
+}
+
+#[test]
+fn yielding_outside_a_stream_fails_the_call() {
+    match count_to().try_call(&[Value::Integer(1)]) {
+        Err(CaatError::NonZeroExit { code: Some(1), stderr, .. }) => {
+            assert!(stderr.contains("does not accept a stream"), "{}", stderr);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,125 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use caat_rust::{CaatError, CallOptions, CancellationToken, ForeignFunction, Value};
+
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+fn sleep() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("sleep").to_str().unwrap())
+}
+
+fn report_path() -> PathBuf {
+    let n = REPORTS.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("caat-sleep-{}-{}", std::process::id(), n))
+}
+
+/// The pid and socket path the sleep fixture wrote to `report`.
+fn read_report(report: &Path) -> (libc::pid_t, PathBuf) {
+    let contents = std::fs::read_to_string(report).unwrap();
+    let _ = std::fs::remove_file(report);
+    let mut lines = contents.lines();
+    (lines.next().unwrap().parse().unwrap(), PathBuf::from(lines.next().unwrap()))
+}
+
+/// Whether `pid` is gone, reaped rather than a zombie.
+fn gone(pid: libc::pid_t) -> bool {
+    unsafe { libc::kill(pid, 0) == -1 }
+}
+
+fn wait_until_gone(pid: libc::pid_t) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !gone(pid) {
+        assert!(Instant::now() < deadline, "process {} is still around", pid);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn call(millis: u64, report: &Path, extra: &[&str], options: &CallOptions) -> Result<Value, CaatError> {
+    let mut args = vec![Value::from(millis.to_string()), Value::from(report.to_str().unwrap())];
+    args.extend(extra.iter().map(|arg| Value::from(*arg)));
+    sleep().call_with(&args, options)
+}
+
+#[test]
+fn timeouts_terminate_the_callee() {
+    let report = report_path();
+    let started = Instant::now();
+    let timeout = Duration::from_millis(500);
+    match call(10_000, &report, &[], &CallOptions::new().timeout(timeout)) {
+        Err(CaatError::Timeout(after)) => assert_eq!(after, timeout),
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let (pid, socket) = read_report(&report);
+    wait_until_gone(pid);
+    assert!(!socket.exists(), "{} was left behind", socket.display());
+}
+
+#[test]
+fn stubborn_callees_are_killed_after_the_grace_period() {
+    let report = report_path();
+    let options = CallOptions::new().timeout(Duration::from_millis(500)).grace_period(Duration::from_millis(300));
+    let started = Instant::now();
+    assert!(matches!(call(10_000, &report, &["ignore-term"], &options), Err(CaatError::Timeout(_))));
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(800), "returned after {:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(5), "returned after {:?}", elapsed);
+    let (pid, socket) = read_report(&report);
+    wait_until_gone(pid);
+    assert!(!socket.exists(), "{} was left behind", socket.display());
+}
+
+#[test]
+fn callees_left_running_are_still_reaped() {
+    let report = report_path();
+    let options = CallOptions::new().timeout(Duration::from_millis(500)).kill_on_timeout(false);
+    assert!(matches!(call(1500, &report, &[], &options), Err(CaatError::Timeout(_))));
+    let (pid, socket) = read_report(&report);
+    assert!(!gone(pid), "the callee was stopped");
+    assert!(!socket.exists(), "{} was left behind", socket.display());
+    wait_until_gone(pid);
+}
+
+#[test]
+fn cancelling_terminates_the_callee() {
+    let report = report_path();
+    let token = CancellationToken::new();
+    let canceller = {
+        let token = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(500));
+            token.cancel();
+        })
+    };
+    let started = Instant::now();
+    assert!(matches!(call(10_000, &report, &[], &CallOptions::new().cancellation(token)), Err(CaatError::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    canceller.join().unwrap();
+    let (pid, socket) = read_report(&report);
+    wait_until_gone(pid);
+    assert!(!socket.exists(), "{} was left behind", socket.display());
+}
+
+#[test]
+fn cancelled_calls_do_not_start() {
+    let report = report_path();
+    let token = CancellationToken::new();
+    token.cancel();
+    assert!(matches!(call(0, &report, &[], &CallOptions::new().cancellation(token)), Err(CaatError::Cancelled)));
+    assert!(!report.exists());
+}
+
+#[test]
+fn calls_that_finish_in_time_succeed() {
+    let report = report_path();
+    let result = call(0, &report, &[], &CallOptions::new().timeout(Duration::from_secs(30)));
+    assert_eq!(result.unwrap(), Value::from("woke"));
+    let (pid, socket) = read_report(&report);
+    wait_until_gone(pid);
+    assert!(!socket.exists());
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use caat_rust::{Caat, Codec, ForeignFunction, Timestamp, Value};
+
+#[test]
+fn timestamps_are_rfc_3339_on_the_wire() {
+    let launch = Value::Timestamp(Timestamp::from_unix(1_714_566_600, 250_000_000).unwrap());
+    assert_eq!(launch.to_json(), r#"{"type":"Timestamp","value":"2024-05-01T12:30:00.250Z"}"#);
+    assert_eq!(Value::from_json(&launch.to_json()), Some(launch));
+
+    let format = |secs, nanos| Timestamp::from_unix(secs, nanos).unwrap().to_string();
+    assert_eq!(format(0, 0), "1970-01-01T00:00:00Z");
+    assert_eq!(format(-1, 999_999_999), "1969-12-31T23:59:59.999999999Z");
+    assert_eq!(format(951_782_400, 1000), "2000-02-29T00:00:00.000001Z");
+    assert_eq!(format(-62_167_219_200, 0), "0000-01-01T00:00:00Z");
+    assert_eq!(format(253_402_300_799, 0), "9999-12-31T23:59:59Z");
+    assert!(Timestamp::from_unix(253_402_300_800, 0).is_none());
+}
+
+#[test]
+fn timestamps_parse_any_offset() {
+    let utc: Timestamp = "2024-05-01T12:30:00Z".parse().unwrap();
+    assert_eq!("2024-05-01T14:30:00+02:00".parse(), Ok(utc));
+    assert_eq!("2024-05-01 07:30:00.000-05:00".parse(), Ok(utc));
+    assert_eq!(
+        "2024-05-01t12:30:00.1234567891z".parse::<Timestamp>().unwrap().subsec_nanos(),
+        123_456_789,
+    );
+    for bad in ["2024-05-01", "2023-02-29T00:00:00Z", "2024-05-01T24:00:00Z", "2024-05-01T12:30:00", "2024-05-01T12:30:00.Z"] {
+        assert!(bad.parse::<Timestamp>().is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn durations_are_iso_8601_on_the_wire() {
+    let timeout = Value::from(Duration::from_millis(90_500));
+    assert_eq!(timeout.to_json(), r#"{"type":"Duration","value":"PT90.500S"}"#);
+    let parse = |s: &str| {
+        let json = format!(r#"{{"type":"Duration","value":"{}"}}"#, s);
+        Value::from_json(&json).map(|d| Duration::try_from(d).unwrap())
+    };
+    assert_eq!(parse("PT90.5S"), Some(Duration::from_millis(90_500)));
+    assert_eq!(parse("P1DT2H3M4S"), Some(Duration::from_secs(93_784)));
+    assert_eq!(parse("PT0S"), Some(Duration::ZERO));
+    for bad in ["P", "PT", "P1Y", "PT1.S", "PT5M1H", "-PT1S"] {
+        assert_eq!(parse(bad), None, "{}", bad);
+    }
+}
+
+#[test]
+fn system_times_convert_both_ways() {
+    for time in [UNIX_EPOCH, UNIX_EPOCH + Duration::new(1_700_000_000, 7), UNIX_EPOCH - Duration::new(86_400, 1)] {
+        let value = Value::try_from(time).unwrap();
+        assert_eq!(SystemTime::try_from(value), Ok(time));
+    }
+    assert_eq!(Duration::try_from(Value::from(Duration::new(3, 4))), Ok(Duration::new(3, 4)));
+    assert!(SystemTime::try_from(Value::Integer(0)).is_err());
+    assert!(Duration::try_from(Value::String("PT1S".to_string())).is_err());
+}
+
+#[test]
+fn times_round_trip_through_every_codec() {
+    let values = [Value::Timestamp(Timestamp::now()), Value::from(Duration::new(12, 345))];
+    for value in values {
+        for codec in Codec::AVAILABLE {
+            assert_eq!(codec.decode(&codec.encode(&value)).unwrap(), value, "{}", codec);
+        }
+    }
+}
+
+#[test]
+fn times_pass_through_a_call() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    let args = [Value::Timestamp(Timestamp::now()), Value::from(Duration::from_micros(1500))];
+    assert_eq!(echo.try_call(&args).unwrap(), Value::from(args.to_vec()));
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn chrono_types_convert() {
+    let time = chrono::DateTime::from_timestamp(1_714_566_600, 5).unwrap();
+    let value = Value::try_from(time).unwrap();
+    assert_eq!(value.to_string(), "2024-05-01T12:30:00.000000005Z");
+    assert_eq!(chrono::DateTime::try_from(value), Ok(time));
+    let delta = chrono::TimeDelta::milliseconds(1500);
+    assert_eq!(chrono::TimeDelta::try_from(Value::try_from(delta).unwrap()), Ok(delta));
+    assert!(Value::try_from(-delta).is_err());
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn time_types_convert() {
+    let time = time::OffsetDateTime::from_unix_timestamp_nanos(-1_500_000_000).unwrap();
+    let value = Value::try_from(time).unwrap();
+    assert_eq!(value.to_string(), "1969-12-31T23:59:58.500Z");
+    assert_eq!(time::OffsetDateTime::try_from(value), Ok(time));
+    let duration = time::Duration::seconds(90);
+    assert_eq!(time::Duration::try_from(Value::try_from(duration).unwrap()), Ok(duration));
+    assert!(Value::try_from(-duration).is_err());
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use caat_rust::{CaatError, TypedForeignFunction, Value};
+
+fn echo<A: caat_rust::IntoArgs, R: TryFrom<Value>>() -> TypedForeignFunction<A, R>
+where R::Error: std::fmt::Display {
+    TypedForeignFunction::new(common::fixture("echo").to_str().unwrap())
+}
+
+#[test]
+fn arguments_and_result_are_converted() {
+    let echo = echo::<(&str, i64, bool), Vec<Value>>();
+    let result = echo.call(("text", 7, true)).unwrap();
+    assert_eq!(result, vec![Value::from("text"), Value::Integer(7), Value::Boolean(true)]);
+}
+
+#[test]
+fn no_arguments() {
+    let echo = echo::<(), Vec<Value>>();
+    assert_eq!(echo.call(()).unwrap(), Vec::new());
+}
+
+#[test]
+fn wrong_result_type_is_a_decode_error() {
+    let echo = echo::<(i64,), String>();
+    match echo.call((1,)) {
+        Err(CaatError::DecodeError(message)) => assert!(message.contains("String"), "{}", message),
+        other => panic!("expected a decode error, got {:?}", other),
+    }
+}
+
+#[test]
+fn nested_vectors_become_nested_lists() {
+    let rows = vec![vec!["a".to_string(), "b".to_string()], vec![]];
+    let expected = Value::list(vec![Value::from(vec!["a", "b"]), Value::list(vec![])]);
+    assert_eq!(Value::from(rows), expected);
+    assert_eq!(Value::from(vec![vec![1i64], vec![2, 3]]), Value::from(vec![Value::from(vec![1i64]), Value::from(vec![2i64, 3])]));
+    assert_eq!(Value::list(vec![]), Value::List(Box::new([])));
+}
+
+#[test]
+fn typed_lists_are_decoded() {
+    let strings = echo::<(&str, &str), Vec<String>>();
+    assert_eq!(strings.call(("a", "b")).unwrap(), vec!["a".to_string(), "b".to_string()]);
+
+    let nested = echo::<(Vec<i64>, Vec<i64>), Vec<Vec<i64>>>();
+    assert_eq!(nested.call((vec![1, 2], vec![])).unwrap(), vec![vec![1, 2], vec![]]);
+
+    let mixed = echo::<(&str, i64), Vec<String>>();
+    match mixed.call(("a", 1)) {
+        Err(CaatError::DecodeError(message)) => assert!(message.contains("not a string"), "{}", message),
+        other => panic!("expected a decode error, got {:?}", other),
+    }
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use std::process::Command;
+use std::time::{Duration, Instant};
+use caat_rust::{Caat, CaatError, CaatExt, CallOptions, CancellationToken, ForeignFunction, NativeFunction, Value};
+
+fn counter() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("counter").to_str().unwrap())
+}
+
+fn answer(count: i64, args: Vec<Value>) -> Value {
+    Value::from(vec![Value::from(count), Value::from(args)])
+}
+
+/// A callback that keeps the worker calling it busy for ten seconds.
+#[cfg(unix)]
+fn hang() -> Value {
+    NativeFunction::new("hang", |_| {
+        std::thread::sleep(Duration::from_secs(10));
+        Value::Null
+    }).into_value()
+}
+
+/// Waits until `pid` is gone, reaped rather than a zombie.
+#[cfg(unix)]
+fn wait_until_gone(pid: u32) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        assert!(Instant::now() < deadline, "process {} is still around", pid);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn workers_answer_many_calls_in_one_process() {
+    let worker = counter().spawn_worker().unwrap();
+    let pid = worker.pid();
+    assert_eq!(worker.try_call(&[Value::from("a")]).unwrap(), answer(1, vec![Value::from("a")]));
+    assert_eq!(worker.try_call(&[Value::Integer(2), Value::Null]).unwrap(), answer(2, vec![Value::Integer(2), Value::Null]));
+    assert_eq!(worker.try_call(&[]).unwrap(), answer(3, vec![]));
+    assert_eq!(worker.pid(), pid);
+    worker.shutdown().unwrap();
+}
+
+#[test]
+fn serve_loop_answers_once_outside_of_a_worker() {
+    assert_eq!(counter().try_call(&[Value::from("a")]).unwrap(), answer(1, vec![Value::from("a")]));
+    assert_eq!(counter().try_call(&[Value::from("a")]).unwrap(), answer(1, vec![Value::from("a")]));
+}
+
+#[cfg(unix)]
+#[test]
+fn dead_workers_are_restarted() {
+    let worker = counter().spawn_worker().unwrap();
+    assert_eq!(worker.try_call(&[]).unwrap(), answer(1, vec![]));
+    let pid = worker.pid().unwrap();
+    let status = Command::new("kill").args(["-9", &pid.to_string()]).status().unwrap();
+    assert!(status.success());
+    wait_until_gone(pid);
+
+    assert_eq!(worker.try_call(&[]).unwrap(), answer(1, vec![]));
+    assert_eq!(worker.restarts(), 1);
+    assert_ne!(worker.pid(), Some(pid));
+}
+
+#[cfg(unix)]
+#[test]
+fn hung_workers_time_out_and_are_restarted() {
+    let worker = counter().spawn_worker().unwrap();
+    let pid = worker.pid().unwrap();
+    let timeout = Duration::from_millis(300);
+    let options = CallOptions::new().timeout(timeout).kill_on_timeout(false);
+    let started = Instant::now();
+    match worker.call_with(&[hang()], &options) {
+        Err(CaatError::Timeout(after)) => assert_eq!(after, timeout),
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+    wait_until_gone(pid);
+
+    assert_eq!(worker.try_call(&[]).unwrap(), answer(1, vec![]));
+    assert_eq!(worker.restarts(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn worker_calls_can_be_cancelled() {
+    let worker = counter().spawn_worker().unwrap();
+    let pid = worker.pid().unwrap();
+    let token = CancellationToken::new();
+    let canceller = token.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        canceller.cancel();
+    });
+    let options = CallOptions::new().cancellation(token.clone());
+    assert!(matches!(worker.call_with(&[hang()], &options), Err(CaatError::Cancelled)));
+    wait_until_gone(pid);
+    assert!(matches!(worker.call_with(&[], &options), Err(CaatError::Cancelled)));
+
+    let options = CallOptions::new().timeout(Duration::from_secs(5));
+    assert_eq!(worker.call_with(&[], &options).unwrap(), answer(1, vec![]));
+}
+
+#[test]
+fn workers_call_back_into_the_caller() {
+    let double = NativeFunction::new("double", |args| match args {
+        [Value::Integer(i)] => Value::Integer(i * 2),
+        _ => Value::Failure("double takes one integer".to_string()),
+    }).into_value();
+    let worker = counter().spawn_worker().unwrap();
+    assert_eq!(worker.try_call(std::slice::from_ref(&double)).unwrap(), Value::Integer(2));
+    assert_eq!(worker.try_call(&[double]).unwrap(), Value::Integer(4));
+}
+
+#[cfg(unix)]
+#[test]
+fn workers_that_never_connect_time_out() {
+    let timeout = Duration::from_millis(300);
+    let started = Instant::now();
+    match ForeignFunction::new("sleep 30").spawn_worker_with(&CallOptions::new().timeout(timeout)) {
+        Err(CaatError::Timeout(after)) => assert_eq!(after, timeout),
+        Err(other) => panic!("unexpected error: {}", other),
+        Ok(_) => panic!("sleep started as a worker"),
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn commands_without_serve_loop_are_not_workers() {
+    let echo = ForeignFunction::new(common::fixture("echo").to_str().unwrap());
+    match echo.spawn_worker() {
+        Err(CaatError::ProtocolError(message)) => assert!(message.contains("worker mode"), "{}", message),
+        Err(other) => panic!("unexpected error: {}", other),
+        Ok(_) => panic!("echo started as a worker"),
+    }
+}
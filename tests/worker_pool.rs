@@ -0,0 +1,118 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use caat_rust::{Caat, CaatError, CaatExt, ForeignFunction, NativeFunction, PoolOptions, PoolStats, Value, WorkerPool};
+
+fn counter() -> ForeignFunction {
+    ForeignFunction::new(common::fixture("counter").to_str().unwrap())
+}
+
+/// The count the counter fixture answered a single argument call with.
+fn count(answer: Value) -> i64 {
+    match answer {
+        Value::List(list) => i64::try_from(list[0].clone()).unwrap(),
+        other => panic!("unexpected answer: {:?}", other),
+    }
+}
+
+/// A callback that keeps the worker calling it busy until `gate` is free.
+fn hold(gate: Arc<Mutex<()>>) -> Value {
+    NativeFunction::new("hold", move |_| {
+        drop(gate.lock().unwrap_or_else(|e| e.into_inner()));
+        Value::Null
+    }).into_value()
+}
+
+/// Polls the stats of `pool` until `done` holds, failing after five seconds.
+fn wait_for(pool: &WorkerPool, done: impl Fn(&PoolStats) -> bool) -> PoolStats {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let stats = pool.stats();
+        if done(&stats) {
+            return stats;
+        }
+        assert!(Instant::now() < deadline, "gave up waiting, the pool is at {:?}", stats);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn pools_start_min_size_workers_up_front() {
+    let pool = WorkerPool::new(counter(), PoolOptions::new().min_size(2).max_size(4)).unwrap();
+    let stats = pool.stats();
+    assert_eq!((stats.workers, stats.idle, stats.busy, stats.started), (2, 2, 0, 2));
+    pool.shutdown().unwrap();
+}
+
+#[test]
+fn map_keeps_the_order_of_its_inputs() {
+    let pool = WorkerPool::new(counter(), PoolOptions::new().min_size(0).max_size(3)).unwrap();
+    let results = pool.map((0..20).map(Value::Integer));
+    assert_eq!(results.len(), 20);
+    for (i, result) in results.into_iter().enumerate() {
+        match result.unwrap() {
+            Value::List(list) => assert_eq!(list[1], Value::from(vec![Value::Integer(i as i64)])),
+            other => panic!("unexpected answer: {:?}", other),
+        }
+    }
+    let stats = pool.stats();
+    assert_eq!(stats.calls, 20);
+    assert_eq!(stats.failures, 0);
+    assert!(stats.started <= 3, "started {} workers", stats.started);
+}
+
+#[test]
+fn workers_are_recycled_after_max_calls() {
+    let pool = WorkerPool::new(counter(), PoolOptions::new().max_size(1).max_calls(2)).unwrap();
+    let counts = (0..5).map(|_| count(pool.try_call(&[Value::Null]).unwrap())).collect::<Vec<_>>();
+    assert_eq!(counts, vec![1, 2, 1, 2, 1]);
+    assert_eq!(pool.stats().retired, 2);
+}
+
+#[test]
+fn idle_workers_are_shut_down() {
+    let options = PoolOptions::new().min_size(0).max_size(2).idle_timeout(Duration::from_millis(50));
+    let pool = WorkerPool::new(counter(), options).unwrap();
+    pool.try_call(&[Value::Null]).unwrap();
+    assert_eq!(pool.stats().workers, 1);
+    let stats = wait_for(&pool, |stats| stats.workers == 0);
+    assert_eq!(stats.retired, 1);
+}
+
+#[test]
+fn full_queues_turn_calls_away() {
+    let pool = WorkerPool::new(counter(), PoolOptions::new().max_size(1).queue_limit(0)).unwrap();
+    let gate = Arc::new(Mutex::new(()));
+    let closed = gate.lock().unwrap();
+    std::thread::scope(|scope| {
+        let busy = scope.spawn(|| pool.try_call(&[hold(gate.clone())]));
+        wait_for(&pool, |stats| stats.busy == 1);
+        match pool.try_call(&[Value::Null]) {
+            Err(CaatError::QueueFull(0)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        drop(closed);
+        busy.join().unwrap().unwrap();
+    });
+    assert_eq!(count(pool.try_call(&[Value::Null]).unwrap()), 2);
+}
+
+#[test]
+fn map_waits_for_workers_whatever_the_queue_limit() {
+    let pool = WorkerPool::new(counter(), PoolOptions::new().max_size(1).queue_limit(0)).unwrap();
+    let gate = Arc::new(Mutex::new(()));
+    let closed = gate.lock().unwrap();
+    std::thread::scope(|scope| {
+        let busy = scope.spawn(|| pool.try_call(&[hold(gate.clone())]));
+        wait_for(&pool, |stats| stats.busy == 1);
+        let mapped = scope.spawn(|| pool.map(0..3));
+        wait_for(&pool, |stats| stats.queued == 1);
+        drop(closed);
+        busy.join().unwrap().unwrap();
+        let results = mapped.join().unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.into_iter().all(|result| result.is_ok()));
+    });
+    assert_eq!(pool.stats().failures, 0);
+}